@@ -0,0 +1,103 @@
+extern crate proc_macro;
+
+use std::env;
+use std::path::Path;
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{ Ident, Lit, LitStr, Token, parse_macro_input };
+use syn::parse::{ Parse, ParseStream };
+use img_to_array::Converter;
+
+// Arguments of include_image!: the image path followed by key = value options
+struct Args {
+    path: LitStr,
+    options: Vec<(Ident, Lit)>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path: LitStr = input.parse()?;
+        let mut options: Vec<(Ident, Lit)> = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break
+            }
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            options.push((key, input.parse()?));
+        }
+        Ok(Args { path, options })
+    }
+}
+
+// Converts an image at compile time into the same statics and constants as
+// the converter's Rust output:
+//
+//     include_image!("sprite.png", format = "rgb565", palette = "pal.png");
+//
+// Options are the long command line options, with format as another name for
+// colour and name for prefix. Paths are relative to the Cargo.toml of the
+// crate using the macro. Conversion errors become compile errors.
+#[proc_macro]
+pub fn include_image(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as Args);
+    match expand(&args) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(args: &Args) -> syn::Result<proc_macro2::TokenStream> {
+    let root = env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let resolve = |p: &str| Path::new(&root).join(p).to_string_lossy()
+        .into_owned();
+    let error = |span: Span, message: String| syn::Error::new(span, message);
+
+    // Turn the options into command line arguments
+    let path = resolve(&args.path.value());
+    let mut files = vec![path.clone()];
+    let mut options = vec![String::from("--language=rust")];
+    for (key, value) in &args.options {
+        let name = match key.to_string().as_str() {
+            "format" => String::from("colour"),
+            "name" => String::from("prefix"),
+            "language" | "output" | "header" | "split" | "manifest" | "help"
+                | "reverse" =>
+                return Err(error(key.span(), format!("Option {} can't be \
+                    used with include_image!", key))),
+            other => other.replace('_', ""),
+        };
+        match value {
+            Lit::Str(s) if name == "palette" => {
+                let palette = resolve(&s.value());
+                options.push(format!("--palette={}", palette));
+                files.push(palette);
+            },
+            Lit::Str(s) if name == "diagnostic" || name == "preview" =>
+                options.push(format!("--{}={}", name, resolve(&s.value()))),
+            Lit::Str(s) => options.push(format!("--{}={}", name, s.value())),
+            Lit::Int(i) => options.push(format!("--{}={}", name, i.value())),
+            Lit::Bool(b) => if b.value {
+                options.push(format!("--{}", name));
+            },
+            _ => return Err(error(key.span(), format!("Option {} has to be a \
+                string, integer or bool", key))),
+        }
+    }
+
+    let converter = Converter::from_options(&options)
+        .map_err( |e| error(args.path.span(), e.to_string()) )?;
+    let output = converter.convert_file(&path)
+        .map_err( |e| error(args.path.span(), e.to_string()) )?;
+    let items: proc_macro2::TokenStream = String::from_utf8_lossy(&output)
+        .parse()
+        .map_err( |e| error(args.path.span(), format!("{:?}", e)) )?;
+
+    // Including the files makes the crate rebuild when they change
+    Ok(quote! {
+        #( const _: &[u8] = include_bytes!(#files); )*
+        #items
+    })
+}
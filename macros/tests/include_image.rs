@@ -0,0 +1,37 @@
+use img_to_array_macros::include_image;
+
+// Paths are relative to the macros crate
+include_image!("../doc/example_heart.png");
+include_image!("../doc/example_heart.png", format = "rgb332",
+    palette = "../doc/example_heart_palette.png", palsize = 2,
+    name = "heart");
+include_image!("../doc/example_heart.png", nopalette = true, name = "raw");
+
+#[test]
+fn default_options() {
+    assert_eq!(EXAMPLE_HEART_WIDTH, 7);
+    assert_eq!(EXAMPLE_HEART_HEIGHT, 7);
+    assert_eq!(EXAMPLE_HEART_PALETTE_LEN, 4);
+    assert_eq!(EXAMPLE_HEART_PALETTE.len(), 4);
+    assert_eq!(EXAMPLE_HEART_IMAGE_DATA.len(), 49);
+    assert_eq!(&EXAMPLE_HEART_IMAGE_DATA[..4], &[0, 1, 1, 0]);
+}
+
+#[test]
+fn packed_indices() {
+    assert_eq!(HEART_WIDTH, 7);
+    assert_eq!(HEART_BPP, 2);
+    let palette: &[u8] = &HEART_PALETTE;
+    assert_eq!(palette, &[0x00, 0xA6, 0xE9, 0xFB]);
+    assert_eq!(HEART_IMAGE_DATA.len(), 13);
+    assert_eq!(HEART_IMAGE_DATA[0], 0x28);
+}
+
+#[test]
+fn raw_colours() {
+    assert_eq!(RAW_WIDTH, 7);
+    assert_eq!(RAW_BPP, 16);
+    let image_data: &[u16] = &RAW_IMAGE_DATA;
+    assert_eq!(image_data.len(), 49);
+    assert_eq!(image_data[..2], [0x0000, 0xEA6E]);
+}
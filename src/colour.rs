@@ -0,0 +1,574 @@
+use crate::output::Element;
+
+#[derive(Debug)]
+pub enum ColourFormat {
+    RGB332, RGB444, RGB555, RGB565, RGB666, RGB,
+    GREY, GREY4, MONO,
+    ARGB1555, ARGB4444, ARGB8888,
+}
+
+// Order of the colour channels in values, from most to least significant
+#[derive(Debug, Clone, Copy)]
+pub enum ChannelOrder { Rgb, Bgr }
+
+// Order of the bytes in values, either as they are when written as numbers or
+// with the bytes reversed
+#[derive(Debug, Clone, Copy)]
+pub enum ByteOrder { Native, Swapped }
+
+impl ColourFormat {
+    // Converts a colour to its value in this format with the given channel
+    // and byte order. Greyscale formats have no channels to reorder.
+    pub(crate) fn encode_ordered(&self, rgb: &Rgb, channel_order: ChannelOrder,
+        byte_order: ByteOrder) -> u32 {
+        let value = match (channel_order, self.grey_bits()) {
+            (ChannelOrder::Bgr, None) => self.encode(&rgb.bgr()),
+            _ => self.encode(rgb),
+        };
+        match byte_order {
+            ByteOrder::Native => value,
+            ByteOrder::Swapped => {
+                let bytes = u32::from(self.bytes());
+                value.swap_bytes() >> (32 - 8 * bytes)
+            },
+        }
+    }
+
+    // Converts a colour to its value in this format
+    pub(crate) fn encode(&self, rgb: &Rgb) -> u32 {
+        match self {
+            ColourFormat::RGB332 => u32::from(Rgb332::from(rgb).0),
+            ColourFormat::RGB444 => u32::from(Rgb444::from(rgb).0),
+            ColourFormat::RGB555 => u32::from(Rgb555::from(rgb).0),
+            ColourFormat::RGB565 => u32::from(Rgb565::from(rgb).0),
+            ColourFormat::RGB666 => Rgb666::from(rgb).0,
+            ColourFormat::RGB => rgb.0 & 0xFFFFFF,
+            ColourFormat::GREY => u32::from(Grey::from(rgb).0),
+            ColourFormat::GREY4 => u32::from(Grey::from(rgb).0 >> 4),
+            ColourFormat::MONO => u32::from(Grey::from(rgb).0 >> 7),
+            ColourFormat::ARGB1555 => u32::from(Argb1555::from(rgb).0),
+            ColourFormat::ARGB4444 => u32::from(Argb4444::from(rgb).0),
+            ColourFormat::ARGB8888 => rgb.0,
+        }
+    }
+
+    // Converts a value in this format with the given channel and byte order
+    // back to a colour
+    pub(crate) fn decode_ordered(&self, value: u32,
+        channel_order: ChannelOrder, byte_order: ByteOrder) -> Rgb {
+        let value = match byte_order {
+            ByteOrder::Native => value,
+            ByteOrder::Swapped => {
+                let bytes = u32::from(self.bytes());
+                value.swap_bytes() >> (32 - 8 * bytes)
+            },
+        };
+        match channel_order {
+            ChannelOrder::Rgb => self.decode(value),
+            ChannelOrder::Bgr => self.decode(value).bgr(),
+        }
+    }
+
+    // Converts a value in this format back to a colour, expanding every
+    // channel to 8 bits. Formats with alpha keep it in the colour.
+    pub(crate) fn decode(&self, value: u32) -> Rgb {
+        let channel = |shift: u8, bits: u8| expand(value >> shift
+            & ((1u32 << bits) - 1), bits);
+        if let Some(bits) = self.grey_bits() {
+            let grey = channel(0, bits);
+            return Rgb::new(grey, grey, grey)
+        }
+        let [r_bits, g_bits, b_bits] = self.channel_bits();
+        let rgb = Rgb::new(channel(g_bits + b_bits, r_bits),
+            channel(b_bits, g_bits), channel(0, b_bits));
+        match self.alpha_bits() {
+            0 => rgb,
+            bits => Rgb(rgb.0 | u32::from(channel(r_bits + g_bits + b_bits,
+                bits)) << 24),
+        }
+    }
+
+    // Number identifying this format in binary output headers
+    pub(crate) fn id(&self) -> u8 {
+        match self {
+            ColourFormat::RGB332 => 0,
+            ColourFormat::RGB444 => 1,
+            ColourFormat::RGB555 => 2,
+            ColourFormat::RGB565 => 3,
+            ColourFormat::RGB666 => 4,
+            ColourFormat::RGB => 5,
+            ColourFormat::GREY => 6,
+            ColourFormat::GREY4 => 7,
+            ColourFormat::MONO => 8,
+            ColourFormat::ARGB1555 => 9,
+            ColourFormat::ARGB4444 => 10,
+            ColourFormat::ARGB8888 => 11,
+        }
+    }
+
+    // Whether this format keeps the alpha channel of colours
+    pub(crate) fn has_alpha(&self) -> bool {
+        matches!(self, ColourFormat::ARGB1555 | ColourFormat::ARGB4444
+            | ColourFormat::ARGB8888)
+    }
+
+    // Returns the colour closest to the given one that this format can
+    // represent
+    pub(crate) fn nearest(&self, rgb: Rgb) -> Rgb {
+        let round = |value: u8, bits: u8| {
+            let max = (1u32 << bits) - 1;
+            (u32::from(value) * max + 127) / 255
+        };
+        match self.grey_bits() {
+            Some(bits) => {
+                let grey = expand(round(Grey::from(&rgb).0, bits), bits);
+                Rgb::new(grey, grey, grey)
+            },
+            None => {
+                let [r_bits, g_bits, b_bits] = self.channel_bits();
+                Rgb::new(expand(round(rgb.r(), r_bits), r_bits),
+                    expand(round(rgb.g(), g_bits), g_bits),
+                    expand(round(rgb.b(), b_bits), b_bits))
+            },
+        }
+    }
+
+    // Roughly the distance between neighbouring values this format can
+    // represent in each channel
+    pub(crate) fn spread(&self) -> [f32; 3] {
+        let step = |bits: u8| 255.0 / ((1u32 << bits) - 1) as f32;
+        match self.grey_bits() {
+            Some(bits) => [step(bits); 3],
+            None => {
+                let [r_bits, g_bits, b_bits] = self.channel_bits();
+                [step(r_bits), step(g_bits), step(b_bits)]
+            },
+        }
+    }
+
+    // Type of array element used for values in this format
+    pub(crate) fn element(&self) -> Element {
+        Element::with_bits(self.bytes() * 8)
+    }
+
+    // Number of bytes needed for any value in this format
+    pub(crate) fn bytes(&self) -> u8 {
+        match self.value_digits() {
+            2 => 1,
+            4 => 2,
+            5 | 6 => 3,
+            _ => 4,
+        }
+    }
+
+    // Number of hex digits to write values in this format with. Swapped
+    // values use every digit of their bytes since the high bits may be set.
+    pub(crate) fn hex_digits(&self, byte_order: ByteOrder) -> usize {
+        match byte_order {
+            ByteOrder::Native => self.value_digits(),
+            ByteOrder::Swapped => usize::from(self.bytes()) * 2,
+        }
+    }
+
+    // Number of hex digits needed to write any value in this format
+    fn value_digits(&self) -> usize {
+        match self {
+            ColourFormat::RGB332 | ColourFormat::GREY | ColourFormat::GREY4
+                | ColourFormat::MONO => 2,
+            ColourFormat::RGB444 | ColourFormat::RGB555
+                | ColourFormat::RGB565 | ColourFormat::ARGB1555
+                | ColourFormat::ARGB4444 => 4,
+            ColourFormat::RGB666 => 5,
+            ColourFormat::RGB => 6,
+            ColourFormat::ARGB8888 => 8,
+        }
+    }
+
+    // Number of bits used for red, green and blue
+    fn channel_bits(&self) -> [u8; 3] {
+        match self {
+            ColourFormat::RGB332 => [3, 3, 2],
+            ColourFormat::RGB444 | ColourFormat::ARGB4444 => [4, 4, 4],
+            ColourFormat::RGB555 | ColourFormat::ARGB1555 => [5, 5, 5],
+            ColourFormat::RGB565 => [5, 6, 5],
+            ColourFormat::RGB666 => [6, 6, 6],
+            _ => [8, 8, 8],
+        }
+    }
+
+    // Number of bits used for alpha, above the colour channels
+    fn alpha_bits(&self) -> u8 {
+        match self {
+            ColourFormat::ARGB1555 => 1,
+            ColourFormat::ARGB4444 => 4,
+            ColourFormat::ARGB8888 => 8,
+            _ => 0,
+        }
+    }
+
+    // Number of bits used for greyscale formats
+    fn grey_bits(&self) -> Option<u8> {
+        match self {
+            ColourFormat::GREY => Some(8),
+            ColourFormat::GREY4 => Some(4),
+            ColourFormat::MONO => Some(1),
+            _ => None,
+        }
+    }
+}
+
+// Expands a value with the given number of bits to 8 bits by repeating its
+// bits in the low bits, so that the maximum value becomes 0xFF
+fn expand(value: u32, bits: u8) -> u8 {
+    let bits = i32::from(bits);
+    let mut expanded = 0u32;
+    let mut shift = 8 - bits;
+    while shift > -bits {
+        expanded |= if shift >= 0 { value << shift } else { value >> -shift };
+        shift -= bits;
+    }
+    expanded as u8
+}
+
+// Colour as 0x00RRGGBB, or 0xAARRGGBB when alpha is kept for colour formats
+// that have it
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub(crate) struct Rgb(pub(crate) u32);
+
+impl Rgb {
+    pub(crate) fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb(u32::from(r) << 16 | u32::from(g) << 8 | u32::from(b))
+    }
+
+    // Reads the colour of a pixel, keeping its alpha if asked to
+    pub(crate) fn from_pixel(bgra: &image::Bgra<u8>, keep_alpha: bool)
+        -> Self {
+        match keep_alpha {
+            true => Rgb(Rgb::from(bgra).0 | u32::from(bgra[3]) << 24),
+            false => Rgb::from(bgra),
+        }
+    }
+
+    // Returns the colour with red and blue swapped
+    pub(crate) fn bgr(self) -> Self {
+        Rgb(self.0 & 0xFF00FF00 | u32::from(self.b()) << 16
+            | u32::from(self.r()))
+    }
+
+    pub(crate) fn a(self) -> u8 { (self.0 >> 24) as u8 }
+    pub(crate) fn r(self) -> u8 { (self.0 >> 16) as u8 }
+    pub(crate) fn g(self) -> u8 { (self.0 >> 8) as u8 }
+    pub(crate) fn b(self) -> u8 { self.0 as u8 }
+}
+
+impl From<&image::Bgra<u8>> for Rgb {
+    fn from(bgra: &image::Bgra<u8>) -> Self {
+        let r: u32 = u32::from(bgra[2]) << 16;
+        let g: u32 = u32::from(bgra[1]) << 8;
+        let b: u32 = u32::from(bgra[0]);
+        Rgb(r | g | b)
+    }
+}
+
+// Returns whether the pixel is transparent with the given alpha threshold (or
+// None if transparency isn't handled)
+pub(crate) fn is_transparent(pixel: &image::Bgra<u8>,
+    alpha_threshold: Option<u8>) -> bool {
+    alpha_threshold.is_some_and( |threshold| pixel[3] < threshold )
+}
+
+#[derive(Debug)]
+struct Rgb332(u8);
+
+impl From<&Rgb> for Rgb332 {
+    fn from(rgb: &Rgb) -> Self {
+        // 3 most significant bits from red and green, 2 from blue
+        let r: u8 = rgb.r() >> 5 << 5;
+        let g: u8 = rgb.g() >> 5 << 2;
+        let b: u8 = rgb.b() >> 6;
+        Rgb332(r | g | b)
+    }
+}
+
+#[derive(Debug)]
+struct Rgb444(u16);
+
+impl From<&Rgb> for Rgb444 {
+    fn from(rgb: &Rgb) -> Self {
+        // 4 most significant bits from every channel
+        let r: u16 = u16::from(rgb.r()) >> 4 << 8;
+        let g: u16 = u16::from(rgb.g()) >> 4 << 4;
+        let b: u16 = u16::from(rgb.b()) >> 4;
+        Rgb444(r | g | b)
+    }
+}
+
+#[derive(Debug)]
+struct Rgb555(u16);
+
+impl From<&Rgb> for Rgb555 {
+    fn from(rgb: &Rgb) -> Self {
+        // 5 most significant bits from every channel, top bit left unused
+        let r: u16 = u16::from(rgb.r()) >> 3 << 10;
+        let g: u16 = u16::from(rgb.g()) >> 3 << 5;
+        let b: u16 = u16::from(rgb.b()) >> 3;
+        Rgb555(r | g | b)
+    }
+}
+
+#[derive(Debug)]
+struct Rgb565(u16);
+
+impl From<&Rgb> for Rgb565 {
+    fn from(rgb: &Rgb) -> Self {
+        // First we shift to the right to truncate the values to the widths
+        // we want (5 most significant bits from red, 6 from green, 5 from blue)
+        // then shift them back to the left to their places in 565
+        let r: u16 = (((rgb.0 & 0xFF0000) >> 16) as u16) >> 3 << 11;
+        let g: u16 = (((rgb.0 & 0x00FF00) >> 8) as u16) >> 2 << 5;
+        let b: u16 = ((rgb.0 & 0x0000FF) as u16) >> 3;
+        Rgb565(r | g | b)
+    }
+}
+
+#[derive(Debug)]
+struct Rgb666(u32);
+
+impl From<&Rgb> for Rgb666 {
+    fn from(rgb: &Rgb) -> Self {
+        // 6 most significant bits from every channel, in the low 18 bits
+        let r: u32 = u32::from(rgb.r()) >> 2 << 12;
+        let g: u32 = u32::from(rgb.g()) >> 2 << 6;
+        let b: u32 = u32::from(rgb.b()) >> 2;
+        Rgb666(r | g | b)
+    }
+}
+
+#[derive(Debug)]
+struct Argb1555(u16);
+
+impl From<&Rgb> for Argb1555 {
+    fn from(rgb: &Rgb) -> Self {
+        // Most significant bit of alpha, then 5 from every colour channel
+        let a: u16 = u16::from(rgb.a()) >> 7 << 15;
+        Argb1555(a | Rgb555::from(rgb).0)
+    }
+}
+
+#[derive(Debug)]
+struct Argb4444(u16);
+
+impl From<&Rgb> for Argb4444 {
+    fn from(rgb: &Rgb) -> Self {
+        // 4 most significant bits of alpha, then 4 from every colour channel
+        let a: u16 = u16::from(rgb.a()) >> 4 << 12;
+        Argb4444(a | Rgb444::from(rgb).0)
+    }
+}
+
+#[derive(Debug)]
+struct Grey(u8);
+
+impl From<&Rgb> for Grey {
+    fn from(rgb: &Rgb) -> Self {
+        // Luma with the Rec. 601 weights for each channel
+        let luma = 299 * u32::from(rgb.r()) + 587 * u32::from(rgb.g())
+            + 114 * u32::from(rgb.b());
+        Grey(((luma + 500) / 1000) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgb = Rgb(0xFFFFFF);
+    const GREY: Rgb = Rgb(0x808080);
+    const RED: Rgb = Rgb(0xFF0000);
+
+    #[test]
+    fn encode_known_values() {
+        let cases = [
+            (ColourFormat::RGB332, [0xFF, 0x92, 0xE0]),
+            (ColourFormat::RGB444, [0xFFF, 0x888, 0xF00]),
+            (ColourFormat::RGB555, [0x7FFF, 0x4210, 0x7C00]),
+            (ColourFormat::RGB565, [0xFFFF, 0x8410, 0xF800]),
+            (ColourFormat::RGB666, [0x3FFFF, 0x20820, 0x3F000]),
+            (ColourFormat::RGB, [0xFFFFFF, 0x808080, 0xFF0000]),
+            (ColourFormat::GREY, [0xFF, 0x80, 0x4C]),
+            (ColourFormat::GREY4, [0xF, 0x8, 0x4]),
+            (ColourFormat::MONO, [0x1, 0x1, 0x0]),
+        ];
+        for (format, values) in &cases {
+            assert_eq!([format.encode(&WHITE), format.encode(&GREY),
+                format.encode(&RED)], *values, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn decode_known_values() {
+        let cases = [
+            (ColourFormat::RGB332, 0x92, 0x9292AA),
+            (ColourFormat::RGB444, 0x888, 0x888888),
+            (ColourFormat::RGB555, 0x4210, 0x848484),
+            (ColourFormat::RGB565, 0x8410, 0x848284),
+            (ColourFormat::RGB565, 0xF800, 0xFF0000),
+            (ColourFormat::RGB666, 0x20820, 0x828282),
+            (ColourFormat::RGB, 0x123456, 0x123456),
+            (ColourFormat::GREY, 0x4C, 0x4C4C4C),
+            (ColourFormat::GREY4, 0x8, 0x888888),
+            (ColourFormat::MONO, 0x1, 0xFFFFFF),
+        ];
+        for (format, value, colour) in &cases {
+            assert_eq!(format.decode(*value), Rgb(*colour), "{:?}", format);
+        }
+        // The maximum value of every format decodes to white
+        for (format, max) in &[(ColourFormat::RGB332, 0xFF),
+            (ColourFormat::RGB444, 0xFFF), (ColourFormat::RGB555, 0x7FFF),
+            (ColourFormat::RGB565, 0xFFFF), (ColourFormat::RGB666, 0x3FFFF),
+            (ColourFormat::GREY4, 0xF), (ColourFormat::MONO, 0x1)] {
+            assert_eq!(format.decode(*max), WHITE, "{:?}", format);
+            assert_eq!(format.decode(0), Rgb(0), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn alpha_formats() {
+        let colour = Rgb(0x80FF8040);
+        assert_eq!(ColourFormat::ARGB8888.encode(&colour), 0x80FF8040);
+        assert_eq!(ColourFormat::ARGB4444.encode(&colour), 0x8F84);
+        assert_eq!(ColourFormat::ARGB1555.encode(&colour), 0xFE08);
+        assert_eq!(ColourFormat::ARGB1555.encode(&Rgb(0x7FFFFFFF)), 0x7FFF);
+        assert_eq!(ColourFormat::ARGB1555.encode(&WHITE), 0x7FFF);
+
+        assert_eq!(ColourFormat::ARGB8888.decode(0x80FF8040),
+            Rgb(0x80FF8040));
+        assert_eq!(ColourFormat::ARGB4444.decode(0x8F84), Rgb(0x88FF8844));
+        assert_eq!(ColourFormat::ARGB1555.decode(0x8000), Rgb(0xFF000000));
+        assert_eq!(ColourFormat::ARGB1555.decode(0x7FFF), WHITE);
+
+        // Pixels keep their alpha only when asked to
+        let pixel = image::Bgra([0x40, 0x80, 0xFF, 0x80]);
+        assert_eq!(Rgb::from_pixel(&pixel, true), colour);
+        assert_eq!(Rgb::from_pixel(&pixel, false), Rgb(0xFF8040));
+        assert!(ColourFormat::ARGB4444.has_alpha());
+        assert!(!ColourFormat::RGB.has_alpha());
+    }
+
+    #[test]
+    fn channel_and_byte_order() {
+        let encode = |format: ColourFormat, colour: Rgb, bgr: bool,
+            swapped: bool| format.encode_ordered(&colour, match bgr {
+                true => ChannelOrder::Bgr,
+                false => ChannelOrder::Rgb,
+            }, match swapped {
+                true => ByteOrder::Swapped,
+                false => ByteOrder::Native,
+            });
+        assert_eq!(encode(ColourFormat::RGB565, RED, false, false), 0xF800);
+        assert_eq!(encode(ColourFormat::RGB565, RED, true, false), 0x001F);
+        assert_eq!(encode(ColourFormat::RGB565, RED, false, true), 0x00F8);
+        assert_eq!(encode(ColourFormat::RGB565, RED, true, true), 0x1F00);
+        assert_eq!(encode(ColourFormat::RGB666, RED, false, true), 0x00F003);
+        assert_eq!(encode(ColourFormat::RGB, RED, true, true), 0xFF0000);
+        assert_eq!(encode(ColourFormat::ARGB8888, Rgb(0x80FF8040), false,
+            true), 0x4080FF80);
+        assert_eq!(encode(ColourFormat::ARGB4444, Rgb(0x80FF8040), true,
+            false), 0x848F);
+        assert_eq!(encode(ColourFormat::GREY, RED, true, true), 0x4C);
+    }
+
+    #[test]
+    fn ordered_round_trip() {
+        let formats = [
+            ColourFormat::RGB332, ColourFormat::RGB444, ColourFormat::RGB555,
+            ColourFormat::RGB565, ColourFormat::RGB666, ColourFormat::RGB,
+            ColourFormat::GREY, ColourFormat::GREY4, ColourFormat::MONO,
+            ColourFormat::ARGB1555, ColourFormat::ARGB4444,
+            ColourFormat::ARGB8888,
+        ];
+        let colours = [0x00000000, 0xFFFFFFFF, 0x80808080, 0x12345678,
+            0xFEDCBA98, 0x00FF0000, 0x7F00FF00, 0xC00000FF];
+        for format in &formats {
+            for &channel_order in &[ChannelOrder::Rgb, ChannelOrder::Bgr] {
+                for &byte_order in &[ByteOrder::Native, ByteOrder::Swapped] {
+                    for &colour in &colours {
+                        let colour = Rgb(match format.has_alpha() {
+                            true => colour,
+                            false => colour & 0xFFFFFF,
+                        });
+                        // Encoding and decoding again gives the same value
+                        let value = format.encode_ordered(&colour,
+                            channel_order, byte_order);
+                        let decoded = format.decode_ordered(value,
+                            channel_order, byte_order);
+                        assert_eq!(format.encode_ordered(&decoded,
+                            channel_order, byte_order), value,
+                            "{:?} {:?} {:?} {:08X}", format, channel_order,
+                            byte_order, colour.0);
+
+                        // and the colour decoded is the original one with
+                        // the bits the format doesn't keep lost
+                        let expected = match format.grey_bits() {
+                            Some(_) => {
+                                let grey = Grey::from(&colour).0;
+                                Rgb(colour.0 & 0xFF000000
+                                    | Rgb::new(grey, grey, grey).0)
+                            },
+                            None => colour,
+                        };
+                        let bits = format.grey_bits().unwrap_or_else( ||
+                            *format.channel_bits().iter().min().unwrap() );
+                        let close = |a: u8, b: u8, bits: u8| (i32::from(a)
+                            - i32::from(b)).abs() < 1 << (8 - bits);
+                        assert!(close(decoded.r(), expected.r(), bits)
+                            && close(decoded.g(), expected.g(), bits)
+                            && close(decoded.b(), expected.b(), bits)
+                            && (format.alpha_bits() == 0 || close(decoded.a(),
+                            expected.a(), format.alpha_bits())),
+                            "{:?} {:?} {:?} {:08X} decoded as {:08X}", format,
+                            channel_order, byte_order, colour.0, decoded.0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn expand_bits() {
+        assert_eq!(expand(0x1, 1), 0xFF);
+        assert_eq!(expand(0x0, 1), 0x00);
+        assert_eq!(expand(0x2, 2), 0xAA);
+        assert_eq!(expand(0x4, 3), 0x92);
+        assert_eq!(expand(0x8, 4), 0x88);
+        assert_eq!(expand(0x10, 5), 0x84);
+        assert_eq!(expand(0x1F, 5), 0xFF);
+        assert_eq!(expand(0x20, 6), 0x82);
+        assert_eq!(expand(0xAB, 8), 0xAB);
+    }
+
+    #[test]
+    fn nearest_colours() {
+        assert_eq!(ColourFormat::RGB565.nearest(GREY), Rgb(0x848284));
+        assert_eq!(ColourFormat::RGB565.nearest(Rgb(0xFEFEFE)), WHITE);
+        assert_eq!(ColourFormat::RGB332.nearest(Rgb(0x202020)),
+            Rgb(0x242400));
+        assert_eq!(ColourFormat::GREY4.nearest(GREY), Rgb(0x888888));
+        assert_eq!(ColourFormat::MONO.nearest(GREY), WHITE);
+        assert_eq!(ColourFormat::MONO.nearest(Rgb(0x7F7F7F)), Rgb(0));
+        assert_eq!(ColourFormat::RGB.nearest(Rgb(0x123456)), Rgb(0x123456));
+
+        // Nearest colours are exactly what their values decode to
+        for format in &[ColourFormat::RGB332, ColourFormat::RGB444,
+            ColourFormat::RGB555, ColourFormat::RGB565, ColourFormat::RGB666,
+            ColourFormat::RGB, ColourFormat::GREY, ColourFormat::GREY4,
+            ColourFormat::MONO] {
+            for &colour in &[0x000000, 0x808080, 0x123456, 0xFEDCBA,
+                0x7F0080, 0xFFFFFF] {
+                let nearest = format.nearest(Rgb(colour));
+                assert_eq!(format.decode(format.encode(&nearest)), nearest,
+                    "{:?} {:06X}", format, colour);
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::io;
+use crate::{ BitOrder, ByteOrder, ChannelOrder, ColourFormat, ColourMetric,
+    Config, Dither, Endianness, Error, Input, Language, PaletteSize, Placement,
+    Quantizer };
+use crate::{ default_prefix, is_identifier, options, options_from_matches,
+    output };
+
+// Converts images in memory instead of reading and writing files, for use
+// from build scripts and other programs:
+//
+//     let source = Converter::new()
+//         .colour_format(ColourFormat::RGB565)
+//         .palette_size(PaletteSize::Bits(4))
+//         .name("heart")
+//         .convert_to_string(&image::open("heart.png")?)?;
+//
+// Starts out with the same defaults as the command line.
+#[derive(Default)]
+pub struct Converter {
+    config: Config,
+    name: Option<String>,
+}
+
+impl Converter {
+    pub fn new() -> Self {
+        Converter::default()
+    }
+
+    // Creates a converter from command line options, without the program name
+    // or any images. Options that only make sense for files, such as the
+    // output file, are accepted but have no effect. Options for converting
+    // several images or reading C files back aren't accepted.
+    pub fn from_options(args: &[String]) -> Result<Self, Error> {
+        let matches = match options().parse(args) {
+            Ok(matches) => matches,
+            Err(e) => return Err(Error::Argument(e.to_string())),
+        };
+        if !matches.free.is_empty() || matches.opt_present("manifest") {
+            return Err(Error::Argument(String::from("Images can't be given \
+                as options")))
+        }
+        for option in &["reverse", "split", "sharedpalette"] {
+            if matches.opt_present(option) {
+                return Err(Error::Argument(format!("--{} can't be used when \
+                    converting a single image", option)))
+            }
+        }
+        let mut converter = Converter {
+            config: options_from_matches(&matches)
+                .map_err(Error::Argument)?,
+            name: None,
+        };
+        if let Some(prefix) = matches.opt_str("prefix") {
+            converter = converter.name(&prefix);
+        }
+        Ok(converter)
+    }
+
+    // Sets the prefix for the names of the arrays and constants (none, or the
+    // file name when converting a file, by default)
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(String::from(name));
+        self
+    }
+
+    pub fn colour_format(mut self, colour_format: ColourFormat) -> Self {
+        self.config.colour_format = colour_format;
+        self
+    }
+
+    pub fn channel_order(mut self, channel_order: ChannelOrder) -> Self {
+        self.config.channel_order = channel_order;
+        self
+    }
+
+    pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.config.byte_order = byte_order;
+        self
+    }
+
+    // Sets the palette file to take the palette from instead of the image
+    pub fn palette_file(mut self, path: &str) -> Self {
+        self.config.palette_path = Some(String::from(path));
+        self
+    }
+
+    // Sets the path of an image highlighting the pixels with colours missing
+    // from the palette file, written when there are any
+    pub fn diagnostic_image(mut self, path: &str) -> Self {
+        self.config.diagnostic_path = Some(String::from(path));
+        self
+    }
+
+    // Writes colour values directly instead of palette indices
+    pub fn no_palette(mut self, no_palette: bool) -> Self {
+        self.config.no_palette = no_palette;
+        self
+    }
+
+    pub fn palette_size(mut self, palette_size: PaletteSize) -> Self {
+        self.config.palette_size = palette_size;
+        self
+    }
+
+    pub fn bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.config.bit_order = bit_order;
+        self
+    }
+
+    pub fn pad_rows(mut self, pad_rows: bool) -> Self {
+        self.config.pad_rows = pad_rows;
+        self
+    }
+
+    // Reduces the colours in the image with the given method if there are too
+    // many for the palette, to at most the given number of colours if there
+    // is one
+    pub fn quantize(mut self, quantizer: Quantizer, colours: Option<usize>)
+        -> Self {
+        self.config.quantizer = Some(quantizer);
+        self.config.quantize_colours = colours;
+        self
+    }
+
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.config.dither = Some(dither);
+        self
+    }
+
+    // Maps colours missing from the palette file to the nearest palette
+    // colour according to the given metric
+    pub fn nearest(mut self, metric: ColourMetric) -> Self {
+        self.config.nearest = Some(metric);
+        self
+    }
+
+    // Reserves the given palette index for transparent pixels
+    pub fn transparent(mut self, index: usize) -> Self {
+        self.config.transparent_index = Some(index);
+        self
+    }
+
+    pub fn alpha_threshold(mut self, threshold: u8) -> Self {
+        self.config.alpha_threshold = threshold;
+        self
+    }
+
+    pub fn mask(mut self, mask: bool) -> Self {
+        self.config.mask = mask;
+        self
+    }
+
+    pub fn language(mut self, language: Language) -> Self {
+        self.config.language = language;
+        self
+    }
+
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.config.endianness = endianness;
+        self
+    }
+
+    pub fn binary_header(mut self, binary_header: bool) -> Self {
+        self.config.binary_header = binary_header;
+        self
+    }
+
+    pub fn placement(mut self, placement: Placement) -> Self {
+        self.config.placement = placement;
+        self
+    }
+
+    pub fn alignment(mut self, bytes: u32) -> Self {
+        self.config.alignment = Some(bytes);
+        self
+    }
+
+    // Sets the path of a PNG of the image as it will be shown after
+    // conversion, scaled up by a whole number factor, written on every
+    // conversion
+    pub fn preview(mut self, path: &str, scale: u32) -> Self {
+        self.config.preview_path = Some(String::from(path));
+        self.config.preview_scale = scale.max(1);
+        self
+    }
+
+    // Converts the image and returns the output in the configured language
+    pub fn convert(&self, img: &image::DynamicImage)
+        -> Result<Vec<u8>, Error> {
+        let name = self.name.as_deref().unwrap_or_default();
+        self.convert_named(img, name)
+    }
+
+    // Reads the image file and converts it, naming the arrays and constants
+    // after the file unless a name is set
+    pub fn convert_file(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let img = match image::open(path) {
+            Ok(img) => img,
+            Err(error) => return Err(Error::ImageOpen {
+                path: String::from(path), error }),
+        };
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => default_prefix(path),
+        };
+        self.convert_named(&img, &name)
+    }
+
+    fn convert_named(&self, img: &image::DynamicImage, name: &str)
+        -> Result<Vec<u8>, Error> {
+        if !name.is_empty() && !is_identifier(name) {
+            return Err(Error::Argument(format!("Invalid symbol prefix {}",
+                name)))
+        }
+        self.config.validate().map_err(Error::Argument)?;
+        let input = Input { path: String::from(name),
+            prefix: String::from(name) };
+        let (document, _) = crate::convert_image(&self.config, &input,
+            img.clone(), None)?;
+        if let Some(path) = &self.config.preview_path {
+            crate::write_preview(&self.config, &document, None, path)?;
+        }
+        Ok(output::write(&self.config, &[document], None))
+    }
+
+    // Decodes an image file already read into memory and converts it
+    pub fn convert_bytes(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        match image::load_from_memory(bytes) {
+            Ok(img) => self.convert(&img),
+            Err(error) => Err(Error::ImageDecode(error)),
+        }
+    }
+
+    // Converts the image and returns the C or Rust source
+    pub fn convert_to_string(&self, img: &image::DynamicImage)
+        -> Result<String, Error> {
+        if let Language::Binary = self.config.language {
+            return Err(Error::Argument(String::from("Binary output can't be \
+                returned as a string")))
+        }
+        // Source output is always built from strings
+        Ok(String::from_utf8_lossy(&self.convert(img)?).into_owned())
+    }
+
+    // Converts the image and writes the output to the writer
+    pub fn write_to<W: io::Write>(&self, img: &image::DynamicImage,
+        writer: &mut W) -> Result<(), Error> {
+        match writer.write_all(&self.convert(img)?) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(Error::Write { path: None, error }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heart() -> image::DynamicImage {
+        image::open(concat!(env!("CARGO_MANIFEST_DIR"),
+            "/doc/example_heart.png")).unwrap()
+    }
+
+    #[test]
+    fn unprefixed_by_default() {
+        let source = Converter::new().language(Language::Rust)
+            .convert_to_string(&heart()).unwrap();
+        assert!(source.contains("pub const WIDTH: usize = 7;"));
+        assert!(source.contains("pub static PALETTE: [u16; 4]"));
+        assert!(source.contains("pub static IMAGE_DATA: [u8; 49]"));
+
+        let source = Converter::new().name("heart")
+            .convert_to_string(&heart()).unwrap();
+        assert!(source.contains("#define HEART_WIDTH 7"));
+        assert!(source.contains("heart_image_data[49]"));
+    }
+
+    #[test]
+    fn options() {
+        let args = |args: &[&str]| args.iter().map( |&a| String::from(a) )
+            .collect::<Vec<String>>();
+        let source = Converter::from_options(&args(&["--palsize=2",
+            "--prefix=heart"])).unwrap().convert_to_string(&heart()).unwrap();
+        assert!(source.contains("heart_image_data[13]"));
+
+        for option in &["--reverse", "--split", "--sharedpalette"] {
+            match Converter::from_options(&args(&[option])) {
+                Err(Error::Argument(message)) => assert_eq!(message, format!(
+                    "{} can't be used when converting a single image",
+                    option)),
+                _ => panic!("{} should be rejected", option),
+            }
+        }
+        assert!(Converter::from_options(&args(&["heart.png"])).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::colour::{ ColourFormat, Rgb, is_transparent };
+
+// Dithering method used when reducing the colours in an image
+#[derive(Debug, Clone, Copy)]
+pub enum Dither { FloydSteinberg, Atkinson, Bayer2, Bayer4, Bayer8 }
+
+// Error diffusion kernels as (x offset, y offset, weight) and the divisor for
+// the weights. Atkinson only diffuses 6/8 of the error on purpose.
+const FLOYD_STEINBERG: ([(i32, i32, f32); 4], f32) =
+    ([(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+const ATKINSON: ([(i32, i32, f32); 6], f32) = ([(1, 0, 1.0), (2, 0, 1.0),
+    (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)], 8.0);
+
+// Replaces every pixel in the image with the colour returned by `closest` for
+// it, dithering with the given method. `spread` is roughly the distance
+// between neighbouring representable values of each channel, which ordered
+// dithering uses to size its offsets. Transparent pixels (if an alpha
+// threshold is given) are left as they are.
+pub fn dither<F>(img: &image::DynamicImage, method: Dither, spread: [f32; 3],
+    alpha_threshold: Option<u8>, mut closest: F) -> image::DynamicImage
+    where F: FnMut(Rgb) -> Rgb {
+    let mut img = img.to_bgra();
+    let (width, height) = img.dimensions();
+
+    // Working copy of the image with room for accumulated errors
+    let mut values: Vec<[f32; 3]> = img.pixels()
+        .map( |p| [f32::from(p[2]), f32::from(p[1]), f32::from(p[0])] )
+        .collect();
+
+    let bayer = match method {
+        Dither::Bayer2 => Some(bayer_matrix(2)),
+        Dither::Bayer4 => Some(bayer_matrix(4)),
+        Dither::Bayer8 => Some(bayer_matrix(8)),
+        _ => None,
+    };
+
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        if is_transparent(pixel, alpha_threshold) {
+            continue
+        }
+        let index = (y * width + x) as usize;
+        let mut value = values[index];
+
+        // Ordered dithering offsets the value by a threshold based on the
+        // position of the pixel
+        if let Some(matrix) = &bayer {
+            let size = matrix.len() as u32;
+            let threshold = matrix[(y % size) as usize][(x % size) as usize];
+            for (v, s) in value.iter_mut().zip(spread.iter()) {
+                *v += threshold * s;
+            }
+        }
+
+        let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+        let chosen = closest(Rgb::new(clamp(value[0]), clamp(value[1]),
+            clamp(value[2])));
+        pixel[0] = chosen.b();
+        pixel[1] = chosen.g();
+        pixel[2] = chosen.r();
+
+        // Error diffusion spreads the difference between the wanted and the
+        // chosen colour to the neighbouring pixels not handled yet
+        let (kernel, divisor): (&[(i32, i32, f32)], f32) = match method {
+            Dither::FloydSteinberg => (&FLOYD_STEINBERG.0, FLOYD_STEINBERG.1),
+            Dither::Atkinson => (&ATKINSON.0, ATKINSON.1),
+            _ => continue,
+        };
+        let error = [value[0] - f32::from(chosen.r()),
+            value[1] - f32::from(chosen.g()),
+            value[2] - f32::from(chosen.b())];
+        for &(dx, dy, weight) in kernel {
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            if nx < 0 || nx >= width as i32 || ny >= height as i32 {
+                continue
+            }
+            let target = &mut values[(ny as u32 * width + nx as u32) as usize];
+            for (t, e) in target.iter_mut().zip(error.iter()) {
+                *t += e * weight / divisor;
+            }
+        }
+    }
+    image::DynamicImage::ImageBgra8(img)
+}
+
+// Reduces the colours in the image to the ones the colour format can
+// represent, dithering with the given method. The resulting colours are the
+// reduced values expanded back to 8 bits per channel.
+pub fn reduce_depth(img: &image::DynamicImage, method: Dither,
+    format: &ColourFormat) -> image::DynamicImage {
+    dither(img, method, format.spread(), None, |rgb| format.nearest(rgb))
+}
+
+// Returns a size x size Bayer matrix with thresholds between -0.5 and 0.5
+fn bayer_matrix(size: usize) -> Vec<Vec<f32>> {
+    let mut matrix: Vec<Vec<u32>> = vec![vec![0]];
+    while matrix.len() < size {
+        let n = matrix.len();
+        let mut next = vec![vec![0; n * 2]; n * 2];
+        for y in 0..n {
+            for x in 0..n {
+                let v = matrix[y][x] * 4;
+                next[y][x] = v;
+                next[y][x + n] = v + 2;
+                next[y + n][x] = v + 3;
+                next[y + n][x + n] = v + 1;
+            }
+        }
+        matrix = next;
+    }
+    let cells = (size * size) as f32;
+    matrix.iter()
+        .map( |row| row.iter()
+            .map( |&v| (v as f32 + 0.5) / cells - 0.5 )
+            .collect() )
+        .collect()
+}
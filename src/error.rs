@@ -0,0 +1,129 @@
+use std::{ error, fmt, io };
+
+// Everything that can go wrong parsing the options or converting images
+#[derive(Debug)]
+pub enum Error {
+    // The help message was asked for instead of a conversion
+    Help,
+    // An option or manifest value is invalid, or options don't go together
+    Argument(String),
+    // A manifest file has an error on the given line, in the given asset
+    Manifest {
+        path: String,
+        line: Option<usize>,
+        asset: Option<usize>,
+        message: String,
+    },
+    // A file or directory couldn't be read
+    Read { path: String, error: io::Error },
+    // An image file couldn't be opened or decoded
+    ImageOpen { path: String, error: image::ImageError },
+    // An image in memory couldn't be decoded
+    ImageDecode(image::ImageError),
+    PaletteOpen { path: String, error: image::ImageError },
+    // The palette file, or the images if there's no palette file, have more
+    // colours than the palette size can index
+    TooManyColours {
+        palette_file: bool,
+        images: usize,
+        colours: usize,
+        palette_size: u8,
+    },
+    // Colours in the image aren't in the palette file, listed in the order
+    // they're first found
+    ColoursNotInPalette(Vec<MissingColour>),
+    // A C file being read back into an image doesn't have the arrays needed
+    // or they don't make up an image
+    Source { path: String, message: String },
+    // An output file couldn't be written, or the output if there's no file
+    Write { path: Option<String>, error: io::Error },
+    // An error converting one of several images
+    InImage { path: String, error: Box<Error> },
+}
+
+// A colour in an image that isn't in the palette file, with the number of
+// pixels that have it and the positions of the first few of them
+#[derive(Debug)]
+pub struct MissingColour {
+    pub colour: u32,
+    pub pixels: usize,
+    pub positions: Vec<(u32, u32)>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Help => write!(f, "Help requested"),
+            Error::Argument(message) => write!(f, "{}", message),
+            Error::Manifest { path, line, asset, message } => {
+                write!(f, "{}", path)?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                if let Some(asset) = asset {
+                    write!(f, ": asset {}", asset)?;
+                }
+                write!(f, ": {}", message)
+            },
+            Error::Read { path, error } => write!(f, "Error reading \"{}\": \
+                {}", path, error),
+            Error::ImageOpen { path, error } => write!(f, "Error opening image \
+                file \"{}\": {}", path, error),
+            Error::ImageDecode(error) => write!(f, "Error decoding image: {}",
+                error),
+            Error::PaletteOpen { path, error } => write!(f, "Error opening \
+                palette file \"{}\": {}", path, error),
+            Error::TooManyColours { palette_file, images, colours,
+                palette_size } => write!(f, "{} too many colours ({}) for \
+                palette size of {}", match (palette_file, images) {
+                    (true, _) => "Palette file has",
+                    (false, 1) => "Image file has",
+                    (false, _) => "Image files have",
+                }, colours, palette_size),
+            Error::ColoursNotInPalette(missing) => {
+                write!(f, "{} {} present in the palette:", missing.len(),
+                    match missing.len() {
+                        1 => "colour isn't",
+                        _ => "colours aren't",
+                    })?;
+                for colour in missing {
+                    write!(f, "\n    {:#08X}: {} {} at", colour.colour,
+                        colour.pixels, match colour.pixels {
+                            1 => "pixel",
+                            _ => "pixels",
+                        })?;
+                    for (i, (x, y)) in colour.positions.iter().enumerate() {
+                        write!(f, "{} ({}, {})", match i {
+                            0 => "",
+                            _ => ",",
+                        }, x, y)?;
+                    }
+                    if colour.pixels > colour.positions.len() {
+                        write!(f, ", ...")?;
+                    }
+                }
+                Ok(())
+            },
+            Error::Source { path, message } => write!(f, "{}: {}", path,
+                message),
+            Error::Write { path: Some(path), error } => write!(f, "Error \
+                writing file \"{}\": {}", path, error),
+            Error::Write { path: None, error } => write!(f, "Error writing \
+                output: {}", error),
+            Error::InImage { path, error } => write!(f, "{}: {}", path, error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Read { error, .. } | Error::Write { error, .. } =>
+                Some(error),
+            Error::ImageOpen { error, .. } | Error::PaletteOpen { error, .. }
+                | Error::ImageDecode(error) => Some(error),
+            Error::InImage { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs;
+use std::path::{ Path, PathBuf };
+use crate::Error;
+
+// Expands an image path given on the command line into the image files it
+// refers to. Directories expand to the image files directly inside them and
+// paths with * or ? wildcards expand to every matching image file, both
+// sorted by name. Anything else is returned as is.
+pub(crate) fn expand(arg: &str) -> Result<Vec<String>, Error> {
+    let path = Path::new(arg);
+    if path.is_dir() {
+        let mut files: Vec<String> = list_dir(path)?.into_iter()
+            .filter( |p| p.is_file() && is_image(p) )
+            .map( |p| p.to_string_lossy().into_owned() )
+            .collect();
+        files.sort();
+        return match files.is_empty() {
+            true => Err(Error::Argument(format!("No image files found in \
+                directory \"{}\"", arg))),
+            false => Ok(files),
+        }
+    }
+    if !has_wildcards(arg) {
+        return Ok(vec![String::from(arg)])
+    }
+
+    // Match the path one component at a time, only listing directories for
+    // components with wildcards in them
+    let mut matched: Vec<PathBuf> = vec![PathBuf::new()];
+    for component in path.components() {
+        let name = component.as_os_str().to_string_lossy();
+        let mut next: Vec<PathBuf> = Vec::new();
+        for base in &matched {
+            match has_wildcards(&name) {
+                true => {
+                    let dir = match base.as_os_str().is_empty() {
+                        true => Path::new("."),
+                        false => base.as_path(),
+                    };
+                    if !dir.is_dir() {
+                        continue
+                    }
+                    for entry in list_dir(dir)? {
+                        let entry_name = match entry.file_name() {
+                            Some(n) => n.to_string_lossy().into_owned(),
+                            None => continue,
+                        };
+                        // Hidden files are only matched explicitly
+                        if entry_name.starts_with('.')
+                            && !name.starts_with('.') {
+                            continue
+                        }
+                        if matches_pattern(&name, &entry_name) {
+                            next.push(base.join(entry_name));
+                        }
+                    }
+                },
+                false => next.push(base.join(name.as_ref())),
+            }
+        }
+        matched = next;
+    }
+
+    let mut files: Vec<String> = matched.into_iter()
+        .filter( |p| p.is_file() && is_image(p) )
+        .map( |p| p.to_string_lossy().into_owned() )
+        .collect();
+    files.sort();
+    match files.is_empty() {
+        true => Err(Error::Argument(format!("No image files match \"{}\"",
+            arg))),
+        false => Ok(files),
+    }
+}
+
+// Returns the paths of everything in a directory
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.filter_map( |e| e.ok() )
+            .map( |e| e.path() ).collect()),
+        Err(error) => Err(Error::Read {
+            path: dir.to_string_lossy().into_owned(), error }),
+    }
+}
+
+// Returns whether the file extension is one of an image format that can be
+// decoded
+fn is_image(path: &Path) -> bool {
+    image::ImageFormat::from_path(path).is_ok()
+}
+
+fn has_wildcards(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+// Returns whether the name matches the pattern, where * matches any number of
+// characters and ? matches exactly one
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last * seen and the name position it was tried at, for
+    // backtracking when the rest of the pattern doesn't match
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    // Let the * take one more character
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all( |&c| c == '*' )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Creates an empty directory in the temp directory with the given files
+    // in it, including any directories in their paths
+    fn temp_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("img_to_array_{}_{}",
+            name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        dir
+    }
+
+    // Expands the pattern inside the directory, returning the matched paths
+    // relative to it
+    fn expand_in(dir: &Path, pattern: &str) -> Vec<String> {
+        let pattern = dir.join(pattern);
+        let prefix = dir.to_string_lossy().into_owned();
+        match expand(&pattern.to_string_lossy()) {
+            Ok(files) => files.iter()
+                .map( |f| f[prefix.len() + 1..].replace('\\', "/") )
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn star() {
+        assert!(matches_pattern("*", "heart.png"));
+        assert!(matches_pattern("*.png", "heart.png"));
+        assert!(matches_pattern("heart*", "heart"));
+        assert!(matches_pattern("h*t.png", "heart.png"));
+        assert!(matches_pattern("**.png", ".png"));
+        assert!(!matches_pattern("*.png", "heart.bmp"));
+        assert!(!matches_pattern("*.png", "heart.png.bak"));
+        assert!(!matches_pattern("h*", "sheart"));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(matches_pattern("frame?.png", "frame1.png"));
+        assert!(matches_pattern("???", "abc"));
+        assert!(!matches_pattern("frame?.png", "frame.png"));
+        assert!(!matches_pattern("frame?.png", "frame10.png"));
+        assert!(!matches_pattern("?", ""));
+    }
+
+    #[test]
+    fn star_backtracks() {
+        assert!(matches_pattern("a*b*c", "aXbYbc"));
+        assert!(matches_pattern("a*b*c", "abc"));
+        assert!(matches_pattern("*a?c", "abcaxc"));
+        assert!(!matches_pattern("a*b*c", "aXbYbd"));
+        assert!(!matches_pattern("a*b*c", "aXcY"));
+    }
+
+    #[test]
+    fn hidden_files() {
+        let dir = temp_dir("hidden", &[".hidden.png", "shown.png"]);
+        assert_eq!(expand_in(&dir, "*.png"), vec!["shown.png"]);
+        assert_eq!(expand_in(&dir, "*"), vec!["shown.png"]);
+        assert_eq!(expand_in(&dir, ".*.png"), vec![".hidden.png"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_images() {
+        let dir = temp_dir("images", &["a.png", "b.bmp", "notes.txt",
+            "Makefile"]);
+        assert_eq!(expand_in(&dir, "*"), vec!["a.png", "b.bmp"]);
+        assert!(expand_in(&dir, "*.txt").is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn wildcard_directories() {
+        let dir = temp_dir("directories", &["a1/x.png", "a2/x.png",
+            "a2/y.png", "b1/x.png"]);
+        assert_eq!(expand_in(&dir, "a?/x.png"), vec!["a1/x.png", "a2/x.png"]);
+        assert_eq!(expand_in(&dir, "*/x.png"),
+            vec!["a1/x.png", "a2/x.png", "b1/x.png"]);
+        assert_eq!(expand_in(&dir, "a*/*.png"),
+            vec!["a1/x.png", "a2/x.png", "a2/y.png"]);
+        assert!(expand_in(&dir, "c*/x.png").is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
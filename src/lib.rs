@@ -1,330 +1,1541 @@
 extern crate getopts;
 
+mod colour;
+mod converter;
+mod dither;
+mod error;
+mod input;
+mod manifest;
+mod nearest;
+mod output;
+mod preview;
+mod quantize;
+mod reverse;
+
 use std::fs;
+use std::path::Path;
 use std::collections::{ HashSet, HashMap };
-use getopts::Options;
+use getopts::{ Fail, Matches, Options };
+use colour::{ Rgb, is_transparent };
+use manifest::Value;
+use output::{ Array, Document, Element, Item };
+
+pub use colour::{ ByteOrder, ChannelOrder, ColourFormat };
+pub use converter::Converter;
+pub use dither::Dither;
+pub use error::{ Error, MissingColour };
+pub use nearest::ColourMetric;
+pub use output::{ Endianness, Language, Placement };
+pub use quantize::Quantizer;
+
+// An image to convert along with the prefix for the names of its arrays and
+// constants (without the separating underscore)
+#[derive(Debug)]
+pub struct Input {
+    pub path: String,
+    pub prefix: String,
+}
 
 #[derive(Debug)]
 pub struct Config {
-    pub image_path: String,
+    pub inputs: Vec<Input>,
+    // Whether the input is a C file written by the converter to read back
+    // into an image instead
+    pub reverse: bool,
+    // Width of the image read back, as a number or the name of a macro
+    pub width: Option<String>,
     pub palette_path: Option<String>,
+    // Image highlighting the pixels with colours missing from the palette
+    // file, written when there are any
+    pub diagnostic_path: Option<String>,
     pub no_palette: bool,
+    // Whether every image uses the same palette, written only once
+    pub shared_palette: bool,
+    // Prefix for the names of the shared palette and its constants
+    pub palette_prefix: String,
     pub output_path: String,
+    // Whether each image is written to its own output file
+    pub split: bool,
+    pub header_path: Option<String>,
+    // Image of the converted image as it will be shown, scaled up by a whole
+    // number factor
+    pub preview_path: Option<String>,
+    pub preview_scale: u32,
+    pub placement: Placement,
+    // Alignment of arrays in C output in bytes
+    pub alignment: Option<u32>,
+    pub language: Language,
+    pub endianness: Endianness,
+    pub binary_header: bool,
     pub colour_format: ColourFormat,
-    pub palette_size: u8,
+    pub channel_order: ChannelOrder,
+    pub byte_order: ByteOrder,
+    pub palette_size: PaletteSize,
+    pub bit_order: BitOrder,
+    pub pad_rows: bool,
+    pub quantizer: Option<Quantizer>,
+    pub quantize_colours: Option<usize>,
+    pub dither: Option<Dither>,
+    pub nearest: Option<ColourMetric>,
+    pub transparent_index: Option<usize>,
+    pub alpha_threshold: u8,
+    pub mask: bool,
 }
 
-#[derive(Debug)]
-pub enum ColourFormat { RGB565, RGB }
+// The same defaults as the command line, without any images
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            inputs: Vec::new(),
+            reverse: false,
+            width: None,
+            palette_path: None,
+            diagnostic_path: None,
+            no_palette: false,
+            shared_palette: false,
+            palette_prefix: String::new(),
+            output_path: String::from("output.c"),
+            split: false,
+            header_path: None,
+            preview_path: None,
+            preview_scale: 1,
+            placement: Placement::Progmem,
+            alignment: None,
+            language: Language::C,
+            endianness: Endianness::Little,
+            binary_header: false,
+            colour_format: ColourFormat::RGB565,
+            channel_order: ChannelOrder::Rgb,
+            byte_order: ByteOrder::Native,
+            palette_size: PaletteSize::Bits(8),
+            bit_order: BitOrder::MsbFirst,
+            pad_rows: false,
+            quantizer: None,
+            quantize_colours: None,
+            dither: None,
+            nearest: None,
+            transparent_index: None,
+            alpha_threshold: 128,
+            mask: false,
+        }
+    }
+}
 
-#[derive(Debug)]
-struct Rgb565(u16);
+impl Config {
+    // Checks that the options in the config can be used together
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.no_palette && self.shared_palette {
+            return Err(String::from("A shared palette can't be used without a \
+                palette"))
+        }
+        if self.no_palette && self.transparent_index.is_some() {
+            return Err(String::from("A transparent index can't be used \
+                without a palette"))
+        }
+        if self.colour_format.has_alpha() && (self.quantizer.is_some()
+            || self.nearest.is_some()) {
+            return Err(String::from("Quantizing and mapping to the nearest \
+                colour can't be used with colour formats with alpha"))
+        }
+        if self.dither.is_some() && !self.no_palette
+            && self.quantizer.is_none() && self.nearest.is_none() {
+            return Err(String::from("Dithering with a palette needs \
+                quantizing or mapping to the nearest colour"))
+        }
+        if self.nearest.is_some() && self.palette_path.is_none() {
+            return Err(String::from("Mapping to the nearest colour needs a \
+                palette file"))
+        }
+        if self.diagnostic_path.is_some() && self.palette_path.is_none() {
+            return Err(String::from("A diagnostic image can only be written \
+                with a palette file"))
+        }
+        if self.diagnostic_path.is_some() && self.nearest.is_some() {
+            return Err(String::from("A diagnostic image can't be written when \
+                mapping to the nearest colour"))
+        }
 
-impl From<&Rgb> for Rgb565 {
-    fn from(rgb: &Rgb) -> Self {
-        // First we shift to the right to truncate the values to the widths
-        // we want (5 most significant bits from red, 6 from green, 5 from blue)
-        // then shift them back to the left to their places in 565
-        let r: u16 = (((rgb.0 & 0xFF0000) >> 16) as u16) >> 3 << 11;
-        let g: u16 = (((rgb.0 & 0x00FF00) >> 8) as u16) >> 2 << 5;
-        let b: u16 = (((rgb.0 & 0x0000FF)) as u16) >> 3;
-        Rgb565(r | g | b)
+        // Every file written needs a path of its own
+        let mut written: HashSet<String> = HashSet::new();
+        for path in self.output_paths().into_iter()
+            .chain(self.header_paths().into_iter().flatten()) {
+            if !written.insert(path.clone()) {
+                return Err(format!("Output file \"{}\" would be written more \
+                    than once", path))
+            }
+        }
+        Ok(())
     }
-}
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-struct Rgb(u32);
+    // Returns the paths of the files the output is written to: the output
+    // path, or with split output, a file for each image named after its
+    // prefix next to the output path. A shared palette is then written to the
+    // output path itself.
+    pub fn output_paths(&self) -> Vec<String> {
+        match self.split && self.inputs.len() > 1 {
+            true => {
+                let mut paths: Vec<String> = Vec::new();
+                if self.shared_palette {
+                    paths.push(self.output_path.clone());
+                }
+                paths.extend(self.inputs.iter()
+                    .map( |input| Path::new(&self.output_path)
+                        .with_file_name(&input.prefix)
+                        .with_extension(Path::new(&self.output_path)
+                            .extension().unwrap_or_default())
+                        .to_string_lossy().into_owned() ));
+                paths
+            },
+            false => vec![self.output_path.clone()],
+        }
+    }
 
-impl From<&image::Bgra<u8>> for Rgb {
-    fn from(bgra: &image::Bgra<u8>) -> Self {
-        let r: u32 = u32::from(bgra[2]) << 16;
-        let g: u32 = u32::from(bgra[1]) << 8;
-        let b: u32 = u32::from(bgra[0]);
-        Rgb(r | g | b)
+    // Returns the path of the header written with each of the output files,
+    // if separate headers are written. Split output files get headers named
+    // after them.
+    fn header_paths(&self) -> Vec<Option<String>> {
+        let paths = self.output_paths();
+        match paths.len() {
+            1 => vec![self.header_path.clone()],
+            _ => paths.iter()
+                .map( |path| self.header_path.as_ref().map( |_| Path::new(path)
+                    .with_extension("h").to_string_lossy().into_owned() ))
+                .collect(),
+        }
     }
-}
 
-fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} IMAGE_PATH [options]", program);
-    print!("{}", opts.usage(&brief));
+    // Returns the alpha threshold below which pixels are transparent, if
+    // transparent pixels are handled separately
+    fn transparency(&self) -> Option<u8> {
+        self.transparent_index.map( |_| self.alpha_threshold )
+    }
 }
 
-pub fn parse_config(args: Vec<String>) -> Result<Config, ()> {
-    // Get program name
-    let program = args[0].clone();
+// Size of palette indices in bits, or automatically chosen to be the smallest
+// size that fits all the colours in the palette
+#[derive(Debug, Clone, Copy)]
+pub enum PaletteSize { Bits(u8), Auto }
+
+// Order in which pixels are packed into a byte when using palette sizes of
+// less than 8 bits
+#[derive(Debug)]
+pub enum BitOrder { MsbFirst, LsbFirst }
+
+// Returns the help message listing the options
+pub fn usage(program: &str) -> String {
+    let brief = format!("Usage: {} IMAGE_PATH... [options]", program);
+    options().usage(&brief)
+}
 
-    // Setup getopts options and flags
+// Sets up the getopts options and flags shared by the command line and
+// manifest assets
+pub(crate) fn options() -> Options {
     let mut opts = Options::new();
-    opts.optopt("c", "colour", "set colour format ([RGB]565, RGB[888]) (565 by \
-        default)", "FORMAT");
+    opts.optopt("c", "colour", "set colour format (RGB332, RGB444, RGB555, \
+        [RGB]565, RGB666, RGB[888], GREY, GREY4, MONO, ARGB1555, ARGB4444, \
+        ARGB[8888]) (565 by default)", "FORMAT");
+    opts.optopt("", "channelorder", "set order of colour channels in colour \
+        values (rgb, bgr) (rgb by default)", "ORDER");
+    opts.optopt("", "byteorder", "set byte order of colour values (native, \
+        swapped) (native by default)", "ORDER");
     opts.optopt("p", "palette", "set palette file", "FILE");
-    opts.optopt("", "palsize", "set palette size in bits (8, 16, 32) (8 by \
-        default)", "SIZE");
+    opts.optopt("", "palsize", "set palette size in bits (1, 2, 4, 8, 16, 32, \
+        auto) (8 by default)", "SIZE");
+    opts.optopt("", "bitorder", "set bit order of packed palette indices (msb, \
+        lsb) (msb by default)", "ORDER");
+    opts.optflag("", "padrows", "start each row of packed palette indices on \
+        a new byte");
+    opts.optopt("", "quantize", "reduce the colours in the image to fit the \
+        palette size if there are too many (median, octree)", "METHOD");
+    opts.optopt("", "colours", "set the number of colours to reduce the image \
+        to when quantizing (palette size limit by default)", "COUNT");
+    opts.optopt("", "dither", "set dithering used when quantizing or reducing \
+        colour depth (none, floyd, atkinson, bayer2, bayer4, bayer8) (none by \
+        default)", "METHOD");
+    opts.optopt("", "nearest", "map colours missing from the palette file to \
+        the nearest palette colour instead of failing (rgb, weighted, lab)",
+        "METRIC");
+    opts.optopt("", "diagnostic", "write a PNG highlighting the pixels with \
+        colours missing from the palette file", "FILE");
+    opts.optflagopt("", "transparent", "reserve a palette index for \
+        transparent pixels (0 by default)", "INDEX");
+    opts.optopt("", "alpha", "set alpha value below which pixels are \
+        transparent (128 by default)", "THRESHOLD");
+    opts.optflag("", "mask", "write a 1 bit per pixel mask of opaque pixels \
+        alongside the image data");
+    opts.optflag("", "sharedpalette", "use one palette for all of the images, \
+        written before their image data");
     opts.optflag("", "nopalette", "don't use a palette, just write colour \
         values directly");
-    opts.optopt("o", "output", "set output file name (output.c by default)",
-        "FILE");
+    opts.optopt("l", "language", "set output language, or raw binary data \
+        (c, rust, bin) (c by default)", "LANG");
+    opts.optopt("", "endian", "set byte order of binary output (little, big) \
+        (little by default)", "ORDER");
+    opts.optflag("", "binheader", "start binary output with a header \
+        describing the data");
+    opts.optopt("o", "output", "set output file name (output.c, output.rs or \
+        output.bin by default)", "FILE");
+    opts.optflag("", "split", "write each image to its own output file named \
+        after its prefix");
+    opts.optflagopt("", "header", "write declarations to a separate C header \
+        (output file name with .h by default)", "FILE");
+    opts.optopt("", "preview", "write a PNG of the image as it will be shown \
+        after conversion", "FILE");
+    opts.optopt("", "previewscale", "scale the preview up by a whole number \
+        factor (1 by default)", "FACTOR");
+    opts.optopt("", "placement", "set where arrays are placed in memory in C \
+        output (progmem, flash, dram, rodata, section, none) (progmem by \
+        default)", "PRESET");
+    opts.optopt("", "section", "set section name for the section placement",
+        "NAME");
+    opts.optopt("", "attribute", "place arrays in memory with a custom \
+        attribute instead of a preset", "ATTR");
+    opts.optopt("", "align", "align arrays in C output to a number of bytes",
+        "BYTES");
+    opts.optopt("", "prefix", "set prefix for array and constant names \
+        (image file name by default, added before it with multiple images)",
+        "NAME");
+    opts.optflag("", "reverse", "read the arrays in a C file written by the \
+        converter back into a PNG image (written to the C file name with .png \
+        by default)");
+    opts.optopt("", "width", "set the width of the image read back, as a \
+        number or a macro name (WIDTH macro in the file by default)", "WIDTH");
+    opts.optopt("", "manifest", "convert the assets listed in a TOML or JSON \
+        manifest file instead", "FILE");
     opts.optflag("h", "help", "print this help message");
-    let matches = match opts.parse(&args[1..]) {
+    opts
+}
+
+// Parses the command line arguments into a config for each conversion to run:
+// a single one, or one for every asset in a manifest if one is given. Returns
+// Error::Help if the help message was asked for.
+pub fn parse_config(args: Vec<String>) -> Result<Vec<Config>, Error> {
+    let matches = match options().parse(&args[1..]) {
         Ok(m) => m,
-        Err(err) => {
-            eprintln!("{}", err.to_string());
-            print_usage(&program, opts);
-            return Err(())
-        }
+        Err(e) => return Err(Error::Argument(e.to_string())),
     };
 
     // Check if the help menu was requested
     if matches.opt_present("h") {
-        print_usage(&program, opts);
-        return Err(())
+        return Err(Error::Help)
     }
 
+    // Read the configs from the manifest if there is one, which has to be the
+    // only option given
+    if let Some(path) = matches.opt_str("manifest") {
+        if !matches.free.is_empty() || args[1..].iter()
+            .filter( |arg| arg.starts_with('-') ).count() > 1 {
+            return Err(Error::Argument(String::from("A manifest can't be used \
+                with other options or images")))
+        }
+        return load_manifest(&path)
+    }
+
+    Ok(vec![config_from_matches(&matches)?])
+}
+
+// Turns parsed options into a config, or returns what was wrong with them
+fn config_from_matches(matches: &Matches) -> Result<Config, Error> {
     // Check that the correct number of inputs were given
-    if matches.free.len() != 1 {
-        eprintln!("No image file specified");
-        print_usage(&program, opts);
-        return Err(())
+    if matches.free.is_empty() {
+        return Err(Error::Argument(String::from("No image file specified")))
+    }
+
+    let mut config = options_from_matches(matches)
+        .map_err(Error::Argument)?;
+
+    // Read the arrays of a single C file back with the prefix given, or of
+    // the first image in it
+    if config.reverse {
+        if matches.free.len() > 1 {
+            return Err(Error::Argument(String::from("Only one file can be \
+                read back at a time")))
+        }
+        let path = matches.free[0].clone();
+        if !matches.opt_present("o") {
+            config.output_path = Path::new(&path).with_extension("png")
+                .to_string_lossy().into_owned();
+        }
+        config.inputs = vec![Input { path,
+            prefix: matches.opt_str("prefix").unwrap_or_default() }];
+        return Ok(config)
+    }
+
+    // Find the image files to convert
+    let mut image_paths: Vec<String> = Vec::new();
+    for arg in &matches.free {
+        image_paths.extend(input::expand(arg)?);
+    }
+
+    // Set the symbol prefix to one specified or one made from the image name.
+    // With multiple images the prefix specified goes before the image name,
+    // and names are numbered if they'd otherwise be the same.
+    let prefix = matches.opt_str("prefix");
+    if let Some(v) = &prefix {
+        if !v.is_empty() && !is_identifier(v) {
+            return Err(Error::Argument(format!("Invalid symbol prefix {}",
+                v)))
+        }
+    }
+    let single = image_paths.len() == 1;
+
+    // A palette shared by several images is named with just the prefix
+    // specified, or after the output file if there isn't one, so that
+    // palettes of different batches can be linked together
+    let palette_prefix = match &prefix {
+        Some(v) => v.clone(),
+        None => default_prefix(&config.output_path),
+    };
+    let mut used: HashSet<String> = HashSet::new();
+    if !single && config.shared_palette && !palette_prefix.is_empty() {
+        used.insert(palette_prefix.clone());
+    }
+    let mut inputs: Vec<Input> = Vec::new();
+    for path in image_paths {
+        let name = match (&prefix, single) {
+            (Some(v), true) => v.clone(),
+            (Some(v), false) if !v.is_empty() => format!("{}_{}", v,
+                default_prefix(&path)),
+            _ => default_prefix(&path),
+        };
+        let mut prefix = name.clone();
+        let mut number = 2;
+        while !used.insert(prefix.clone()) {
+            prefix = format!("{}_{}", name, number);
+            number += 1;
+        }
+        inputs.push(Input { path, prefix });
     }
+    config.palette_prefix = match single {
+        true => inputs[0].prefix.clone(),
+        false => palette_prefix,
+    };
+    config.inputs = inputs;
+    config.validate().map_err(Error::Argument)?;
+    Ok(config)
+}
+
+// Turns parsed options other than the images into a config without any
+// images, or returns what was wrong with them
+pub(crate) fn options_from_matches(matches: &Matches)
+    -> Result<Config, String> {
+    // Set the output language to one specified or the default one
+    let language = match matches.opt_str("l") {
+        Some(v) => match v.as_str() {
+            "c" | "C" => Language::C,
+            "rust" | "Rust" | "rs" => Language::Rust,
+            "bin" | "binary" => Language::Binary,
+            _ => return Err(format!("Unknown output language {}", v)),
+        },
+        None => Language::C,
+    };
 
     // Set the output path to one specified or the default one
     let output_path = match matches.opt_str("o") {
         Some(v) => v,
-        None => String::from("output.c"),
+        None => match language {
+            Language::C => String::from("output.c"),
+            Language::Rust => String::from("output.rs"),
+            Language::Binary => String::from("output.bin"),
+        },
+    };
+
+    // Set the header path if a separate header was requested
+    let split = matches.opt_present("split");
+    if split && matches.opt_str("header").is_some() {
+        return Err(String::from("A header file name can't be given with split \
+            output"))
+    }
+    let header_path = match (matches.opt_present("header"), language) {
+        (false, _) => None,
+        (true, Language::C) => match matches.opt_str("header") {
+            Some(v) => Some(v),
+            None => Some(Path::new(&output_path).with_extension("h")
+                .to_string_lossy().into_owned()),
+        },
+        (true, _) => {
+            return Err(String::from("A separate header can only be written for \
+                C output"))
+        },
+    };
+
+    // Set the memory placement to the custom attribute, the preset specified
+    // or the default one
+    let placement = match (matches.opt_str("attribute"),
+        matches.opt_str("placement")) {
+        (Some(_), Some(_)) => {
+            return Err(String::from("A custom attribute can't be used with a \
+                placement preset"))
+        },
+        (Some(v), None) => Placement::Custom(v),
+        (None, Some(v)) => match v.as_str() {
+            "progmem" | "PROGMEM" => Placement::Progmem,
+            "flash" | "__flash" => Placement::Flash,
+            "dram" | "DRAM_ATTR" => Placement::DramAttr,
+            "rodata" | "ICACHE_RODATA_ATTR" => Placement::IcacheRodataAttr,
+            "section" => match matches.opt_str("section") {
+                Some(name) => Placement::Section(name),
+                None => return Err(String::from("No section name specified")),
+            },
+            "none" | "const" => Placement::Plain,
+            _ => return Err(format!("Unknown memory placement {}", v)),
+        },
+        (None, None) => Placement::Progmem,
+    };
+
+    // Set the alignment of arrays if one was specified
+    let alignment = match matches.opt_str("align") {
+        Some(v) => match v.parse::<u32>() {
+            Ok(bytes) if bytes.is_power_of_two() => Some(bytes),
+            _ => return Err(format!("Invalid alignment {}", v)),
+        },
+        None => None,
+    };
+    let c_options = ["placement", "section", "attribute", "align"];
+    if !matches!(language, Language::C) && matches.opts_present(&c_options
+        .iter().map( |o| String::from(*o) ).collect::<Vec<_>>()) {
+        return Err(String::from("Memory placement and alignment can only be \
+            set for C output"))
+    }
+
+    // Set the binary byte order to one specified or the default one
+    let endianness = match matches.opt_str("endian") {
+        Some(v) => match v.as_str() {
+            "little" | "le" => Endianness::Little,
+            "big" | "be" => Endianness::Big,
+            _ => return Err(format!("Unknown byte order {}", v)),
+        },
+        None => Endianness::Little,
     };
 
     // Set the colour format to one specified or the default one
     let colour_format = match matches.opt_str("c") {
-        Some(v) => match v.as_str() {
+        Some(v) => match v.to_uppercase().as_str() {
+            "RGB332" | "332" => ColourFormat::RGB332,
+            "RGB444" | "444" => ColourFormat::RGB444,
+            "RGB555" | "555" => ColourFormat::RGB555,
             "RGB565" | "565" => ColourFormat::RGB565,
+            "RGB666" | "666" => ColourFormat::RGB666,
             "RGB" | "RGB888" | "888" => ColourFormat::RGB,
-            _ => {
-                eprintln!("Unknown colour format {}", v);
-                print_usage(&program, opts);
-                return Err(())
-            }
+            "GREY" | "GRAY" | "GREY8" | "GRAY8" => ColourFormat::GREY,
+            "GREY4" | "GRAY4" => ColourFormat::GREY4,
+            "MONO" => ColourFormat::MONO,
+            "ARGB1555" | "1555" => ColourFormat::ARGB1555,
+            "ARGB4444" | "4444" => ColourFormat::ARGB4444,
+            "ARGB" | "ARGB8888" | "8888" => ColourFormat::ARGB8888,
+            _ => return Err(format!("Unknown colour format {}", v)),
         },
         None => ColourFormat::RGB565,
     };
 
+    // Set the channel order to one specified or the default one
+    let channel_order = match matches.opt_str("channelorder") {
+        Some(v) => match v.as_str() {
+            "rgb" | "RGB" => ChannelOrder::Rgb,
+            "bgr" | "BGR" => ChannelOrder::Bgr,
+            _ => return Err(format!("Unknown channel order {}", v)),
+        },
+        None => ChannelOrder::Rgb,
+    };
+
+    // Set the byte order to one specified or the default one
+    let byte_order = match matches.opt_str("byteorder") {
+        Some(v) => match v.as_str() {
+            "native" => ByteOrder::Native,
+            "swapped" | "swap" => ByteOrder::Swapped,
+            _ => return Err(format!("Unknown byte order {}", v)),
+        },
+        None => ByteOrder::Native,
+    };
+
     // Set the palette size to one specified or the default one
     let palette_size = match matches.opt_str("palsize") {
         Some(v) => match v.as_str() {
-            "8" => 8,
-            "16" => 16,
-            "32" => 32,
-            _ => {
-                eprintln!("Unknown palette size {}", v);
-                print_usage(&program, opts);
-                return Err(())
-            }
+            "1" => PaletteSize::Bits(1),
+            "2" => PaletteSize::Bits(2),
+            "4" => PaletteSize::Bits(4),
+            "8" => PaletteSize::Bits(8),
+            "16" => PaletteSize::Bits(16),
+            "32" => PaletteSize::Bits(32),
+            "auto" => PaletteSize::Auto,
+            _ => return Err(format!("Unknown palette size {}", v)),
+        },
+        None => PaletteSize::Bits(8),
+    };
+
+    // Set the bit order to one specified or the default one
+    let bit_order = match matches.opt_str("bitorder") {
+        Some(v) => match v.as_str() {
+            "msb" | "MSB" => BitOrder::MsbFirst,
+            "lsb" | "LSB" => BitOrder::LsbFirst,
+            _ => return Err(format!("Unknown bit order {}", v)),
+        },
+        None => BitOrder::MsbFirst,
+    };
+
+    // Set the quantization method if one was specified
+    let quantizer = match matches.opt_str("quantize") {
+        Some(v) => match v.as_str() {
+            "median" | "mediancut" => Some(Quantizer::MedianCut),
+            "octree" => Some(Quantizer::Octree),
+            _ => return Err(format!("Unknown quantization method {}", v)),
         },
-        None => 8,
+        None => None,
+    };
+
+    // Set the number of colours to quantize to if one was specified
+    let quantize_colours = match matches.opt_str("colours") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(count) if count > 0 => Some(count),
+            _ => return Err(format!("Invalid colour count {}", v)),
+        },
+        None => None,
+    };
+
+    // Set the dithering method if one was specified
+    let dither = match matches.opt_str("dither") {
+        Some(v) => match v.as_str() {
+            "none" => None,
+            "floyd" | "floyd-steinberg" => Some(Dither::FloydSteinberg),
+            "atkinson" => Some(Dither::Atkinson),
+            "bayer2" => Some(Dither::Bayer2),
+            "bayer4" => Some(Dither::Bayer4),
+            "bayer8" => Some(Dither::Bayer8),
+            _ => return Err(format!("Unknown dithering method {}", v)),
+        },
+        None => None,
+    };
+
+    // Set the metric for mapping colours to a palette if one was specified
+    let nearest = match matches.opt_str("nearest") {
+        Some(v) => match v.as_str() {
+            "rgb" => Some(ColourMetric::Rgb),
+            "weighted" => Some(ColourMetric::Weighted),
+            "lab" => Some(ColourMetric::Lab),
+            _ => return Err(format!("Unknown colour metric {}", v)),
+        },
+        None => None,
+    };
+
+    // Set the transparent palette index if transparency is used
+    let transparent_index = match matches.opt_present("transparent") {
+        true => match matches.opt_str("transparent") {
+            Some(v) => match v.parse::<usize>() {
+                Ok(index) => Some(index),
+                Err(_) => return Err(format!("Invalid transparent index {}",
+                    v)),
+            },
+            None => Some(0),
+        },
+        false => None,
+    };
+
+    // Set the preview scale to one specified or the default one
+    let preview_scale = match matches.opt_str("previewscale") {
+        Some(v) => match v.parse::<u32>() {
+            Ok(factor) if factor > 0 => factor,
+            _ => return Err(format!("Invalid preview scale {}", v)),
+        },
+        None => 1,
+    };
+
+    // Set the alpha threshold to one specified or the default one
+    let alpha_threshold = match matches.opt_str("alpha") {
+        Some(v) => match v.parse::<u8>() {
+            Ok(threshold) => threshold,
+            Err(_) => return Err(format!("Invalid alpha threshold {}", v)),
+        },
+        None => 128,
     };
 
     let palette_path = matches.opt_str("p");
     let no_palette = matches.opt_present("nopalette");
-    let image_path = matches.free[0].clone();
+    let shared_palette = matches.opt_present("sharedpalette");
 
     Ok(Config {
-        image_path,
+        inputs: Vec::new(),
+        reverse: matches.opt_present("reverse"),
+        width: matches.opt_str("width"),
         palette_path,
+        diagnostic_path: matches.opt_str("diagnostic"),
         no_palette,
+        shared_palette,
+        palette_prefix: String::new(),
         output_path,
+        split,
+        header_path,
+        preview_path: matches.opt_str("preview"),
+        preview_scale,
+        placement,
+        alignment,
+        language,
+        endianness,
+        binary_header: matches.opt_present("binheader"),
         colour_format,
+        channel_order,
+        byte_order,
         palette_size,
+        bit_order,
+        pad_rows: matches.opt_present("padrows"),
+        quantizer,
+        quantize_colours,
+        dither,
+        nearest,
+        transparent_index,
+        alpha_threshold,
+        mask: matches.opt_present("mask"),
     })
 }
 
-pub fn convert(config: &Config) -> Result<(), String> {
-    let mut output = String::from("#include <stdint.h>\n");
+// Reads the manifest file at the given path and returns a config for every
+// asset in it. Assets have the same keys as the long command line options,
+// along with image (or images) for the image paths and name as another name
+// for prefix. Paths are relative to the manifest file.
+fn load_manifest(path: &str) -> Result<Vec<Config>, Error> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => return Err(Error::Read { path: String::from(path),
+            error }),
+    };
+    let json = Path::new(path).extension().is_some_and( |e| e == "json" );
+    let manifest = match manifest::parse(&text, json) {
+        Ok(manifest) => manifest,
+        Err((line, message)) => return Err(Error::Manifest {
+            path: String::from(path), line: Some(line), asset: None, message }),
+    };
+    if manifest.assets.is_empty() {
+        return Err(Error::Manifest { path: String::from(path), line: None,
+            asset: None, message: String::from("No assets listed") })
+    }
+    let dir = Path::new(path).parent().unwrap_or_else( || Path::new("") );
+    let resolve = |p: &str| dir.join(p).to_string_lossy().into_owned();
+
+    let opts = options();
+    let mut configs: Vec<Config> = Vec::new();
+    let mut outputs: HashMap<String, usize> = HashMap::new();
+    for (number, asset) in manifest.assets.iter().enumerate() {
+        let fail = |line: usize, message: String| Error::Manifest {
+            path: String::from(path), line: Some(line),
+            asset: Some(number + 1), message };
+
+        let fields = manifest.fields(asset);
+
+        // Turn the fields into command line arguments
+        let mut args: Vec<String> = Vec::new();
+        let mut images: Vec<String> = Vec::new();
+        for field in &fields {
+            let key = match field.key.as_str() {
+                "name" => "prefix",
+                "images" => "image",
+                "manifest" | "help" => return Err(fail(field.line,
+                    format!("Unknown key {}", field.key))),
+                key => key,
+            };
+            match (key, &field.value) {
+                ("image", Value::String(p)) => images.push(resolve(p)),
+                ("image", Value::Array(values)) => for value in values {
+                    match value {
+                        Value::String(p) => images.push(resolve(p)),
+                        _ => return Err(fail(field.line, String::from(
+                            "Image paths have to be strings"))),
+                    }
+                },
+                ("image", _) => return Err(fail(field.line, String::from(
+                    "Image paths have to be strings"))),
+                ("palette", Value::String(p)) | ("output", Value::String(p))
+                    | ("header", Value::String(p))
+                    | ("diagnostic", Value::String(p))
+                    | ("preview", Value::String(p)) =>
+                    args.push(format!("--{}={}", key, resolve(p))),
+                (_, Value::String(v)) => args.push(format!("--{}={}", key, v)),
+                (_, Value::Integer(v)) => args.push(format!("--{}={}", key,
+                    v)),
+                (_, Value::Bool(enabled)) => {
+                    // Options that need a value can't be switched on or off
+                    let flag = format!("--{}", key);
+                    let parsed = opts.parse(&[&flag]);
+                    if let Err(Fail::ArgumentMissing(_)) = parsed {
+                        return Err(fail(field.line, format!("{} needs a \
+                            value, not true or false", field.key)))
+                    }
+                    if *enabled {
+                        args.push(flag);
+                    }
+                },
+                (_, Value::Array(_)) => return Err(fail(field.line,
+                    format!("{} can't be a list", key))),
+            }
+        }
+        if images.is_empty() {
+            return Err(fail(asset.line, String::from("No image given")))
+        }
+        args.push(String::from("--"));
+        args.extend(images);
+
+        // Point errors about a single key at the line the key is on
+        let matches = opts.parse(&args).map_err( |e| {
+            let (name, message) = match &e {
+                Fail::UnrecognizedOption(name) => (name,
+                    format!("Unknown key {}", name)),
+                Fail::ArgumentMissing(name) => (name,
+                    format!("{} needs a value", name)),
+                Fail::UnexpectedArgument(name) => (name,
+                    format!("{} has to be true or false", name)),
+                Fail::OptionMissing(name) | Fail::OptionDuplicated(name) =>
+                    (name, e.to_string()),
+            };
+            let line = fields.iter()
+                .find( |f| &f.key == name
+                    || (f.key == "name" && name == "prefix") )
+                .map_or(asset.line, |f| f.line );
+            fail(line, message)
+        })?;
+        let config = config_from_matches(&matches)
+            .map_err( |e| fail(asset.line, e.to_string()) )?;
 
-    // Read in the image to convert
-    let img = match image::open(&config.image_path) {
-        Ok(img) => img,
-        Err(e) => return Err(format!("Error opening image file \"{}\": {}",
-            &config.image_path, e.to_string())),
+        // Assets can't overwrite each other's output
+        for output in config.output_paths() {
+            if let Some(other) = outputs.insert(output.clone(), number + 1) {
+                return Err(fail(asset.line, format!("Output file \"{}\" is \
+                    also written by asset {}", output, other)))
+            }
+        }
+        configs.push(config);
+    }
+    Ok(configs)
+}
+
+// Returns whether the name can be used as an identifier in C and Rust
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars
+            .all( |c| c.is_ascii_alphanumeric() || c == '_' ),
+        _ => false,
+    }
+}
+
+// Makes a symbol prefix out of the image file name by replacing everything
+// that can't be used in an identifier with underscores
+pub(crate) fn default_prefix(image_path: &str) -> String {
+    let stem = Path::new(image_path).file_stem()
+        .map_or_else( || String::from("image"),
+            |stem| stem.to_string_lossy().into_owned() );
+    let mut prefix: String = stem.chars()
+        .map( |c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect();
+    if !is_identifier(&prefix) {
+        prefix.insert(0, '_');
+    }
+    prefix
+}
+
+// Converts the images according to the config and writes the output files.
+// Returns notes about choices made during the conversion for the caller to
+// show to the user.
+pub fn convert(config: &Config) -> Result<Vec<String>, Error> {
+    if config.reverse {
+        return reverse::convert(config)
+    }
+    let mut notes: Vec<String> = Vec::new();
+    let mut documents: Vec<Document> = Vec::new();
+
+    // Read in the images to convert
+    let mut images: Vec<image::DynamicImage> = Vec::new();
+    for input in &config.inputs {
+        images.push(match image::open(&input.path) {
+            Ok(img) => img,
+            Err(error) => return Err(Error::ImageOpen {
+                path: input.path.clone(), error }),
+        });
+    }
+
+    // Construct the palette shared by all the images in a document of its own
+    let shared = match config.shared_palette {
+        true => {
+            notes.extend(reduce_colours(config, &mut images));
+            let palette = construct_palette(config, &images)?;
+            let mut output = Document::new(0, 0, &config.palette_prefix);
+            let palette_size = add_palette(&mut output, config, &palette,
+                &mut notes);
+            documents.push(output);
+            Some((palette, palette_size))
+        },
+        false => None,
     };
 
+    for (input, img) in config.inputs.iter().zip(images) {
+        // Tell the notes and errors of different images apart
+        let single = config.inputs.len() == 1;
+        let shared = shared.as_ref()
+            .map( |(palette, size)| (palette.as_slice(), *size) );
+        let (document, image_notes) = convert_image(config, input, img,
+            shared).map_err( |e| match single {
+                true => e,
+                false => Error::InImage { path: input.path.clone(),
+                    error: Box::new(e) },
+            })?;
+        notes.extend(image_notes.into_iter().map( |note| match single {
+            true => note,
+            false => format!("{}: {}", input.path, note),
+        }));
+        documents.push(document);
+    }
+
+    // Write either every image into the same file or each into its own
+    let paths = config.output_paths();
+    let headers = config.header_paths();
+    match paths.len() {
+        1 => write_files(config, &documents, &paths[0], headers[0].as_deref(),
+            None)?,
+        _ => {
+            // The headers of the images include the header of the shared
+            // palette their indices refer to
+            let palette_header = match config.shared_palette {
+                true => headers[0].as_deref(),
+                false => None,
+            };
+            for (i, (document, path)) in documents.iter().zip(paths.iter())
+                .enumerate() {
+                let include = match i {
+                    0 => None,
+                    _ => palette_header,
+                };
+                write_files(config, std::slice::from_ref(document), path,
+                    headers[i].as_deref(), include)?;
+            }
+        },
+    }
+
+    // Render what the converted images will look like, with the shared
+    // palette in the first document if there is one
+    if let Some(path) = &config.preview_path {
+        let (shared, images) = match config.shared_palette {
+            true => (documents.first(), &documents[1..]),
+            false => (None, &documents[..]),
+        };
+        for (input, document) in config.inputs.iter().zip(images) {
+            let path = match config.inputs.len() {
+                1 => path.clone(),
+                _ => preview_path(path, &input.prefix),
+            };
+            write_preview(config, document, shared, &path)?;
+            notes.push(format!("Preview written to file \"{}\"", path));
+        }
+    }
+
+    Ok(notes)
+}
+
+// Returns the path of the preview of one of several images, with the image's
+// prefix added to the file name
+fn preview_path(path: &str, prefix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().map_or_else(String::new,
+        |stem| stem.to_string_lossy().into_owned() );
+    path.with_file_name(format!("{}_{}.png", stem, prefix)).to_string_lossy()
+        .into_owned()
+}
+
+// Renders the image in the document from its arrays and writes it to a PNG,
+// using the palette of the shared document if it doesn't have its own
+pub(crate) fn write_preview(config: &Config, document: &Document,
+    shared: Option<&Document>, path: &str) -> Result<(), Error> {
+    let image_data = match document.array("image_data") {
+        Some(array) => array,
+        None => return Ok(()),
+    };
+    let palette = document.array("palette")
+        .or_else( || shared.and_then( |shared| shared.array("palette") ) );
+    let img = preview::render(config, &preview::Arrays {
+        width: document.width,
+        height: document.height,
+        bits_per_pixel: document.bits_per_pixel,
+        palette: palette.map( |p| p.values.as_slice() ),
+        image_data: &image_data.values,
+        mask: document.array("image_mask").map( |m| m.values.as_slice() ),
+        transparent_index: config.transparent_index,
+    });
+    preview::scale(img, config.preview_scale)
+        .save_with_format(path, image::ImageFormat::PNG)
+        .map_err( |error| Error::Write { path: Some(String::from(path)),
+            error } )
+}
+
+// Writes the documents to the output file, and to the header file if there
+// is one, which includes another header if given one
+fn write_files(config: &Config, documents: &[Document], path: &str,
+    header_path: Option<&str>, include: Option<&str>) -> Result<(), Error> {
+    if let Some(header_path) = header_path {
+        let header = output::write_c_header(config, documents,
+            header_path, include);
+        if let Err(error) = fs::write(header_path, header) {
+            return Err(Error::Write { path: Some(String::from(header_path)),
+                error })
+        }
+    }
+
+    let output = output::write(config, documents, header_path);
+    if let Err(error) = fs::write(path, output) {
+        return Err(Error::Write { path: Some(String::from(path)), error })
+    }
+    Ok(())
+}
+
+// Reduces the colours in the images if there are too many for the palette
+// being constructed from them, replacing every pixel with the closest colour
+// picked for all of them. Returns a note about the reduction if there was one.
+fn reduce_colours(config: &Config, images: &mut [image::DynamicImage])
+    -> Option<String> {
+    let quantizer = match (config.quantizer, &config.palette_path,
+        config.no_palette) {
+        (Some(quantizer), None, false) => quantizer,
+        _ => return None,
+    };
+    let limit = match config.quantize_colours {
+        Some(count) => count as u64,
+        None => max_palette_len(max_palette_size(config.palette_size)),
+    };
+    // Leave room for the transparent index in the palette
+    let limit = match config.transparent_index {
+        Some(_) => limit.saturating_sub(1).max(1),
+        None => limit,
+    };
+    let colour_count = list_colours(images, config.transparency(),
+        config.colour_format.has_alpha()).len();
+    if colour_count as u64 <= limit {
+        return None
+    }
+
+    let palette = quantize::quantize(images, quantizer, limit as usize,
+        config.transparency());
+    for img in images.iter_mut() {
+        *img = nearest::map_to_palette(img, &palette, ColourMetric::Rgb,
+            config.dither, config.transparency()).0;
+    }
+    let quantized_count = list_colours(images, config.transparency(),
+        config.colour_format.has_alpha()).len();
+    Some(format!("{} quantized from {} to {} colours", match images.len() {
+        1 => "Image",
+        _ => "Images",
+    }, colour_count, quantized_count))
+}
+
+// Adds the palette and the constants that go with it to the output, picking
+// the palette size first if it's chosen automatically. Returns the palette
+// size in bits.
+fn add_palette(output: &mut Document, config: &Config, palette: &[Rgb],
+    notes: &mut Vec<String>) -> u8 {
+    let palette_size = match config.palette_size {
+        PaletteSize::Bits(bits) => bits,
+        PaletteSize::Auto => {
+            let bits = smallest_palette_size(palette.len());
+            output.push(Item::Comment(format!("Palette size chosen \
+                automatically: {} bits for {} colours", bits,
+                palette.len())));
+            notes.push(format!("Palette size chosen automatically: {} bits \
+                for {} colours", bits, palette.len()));
+            bits
+        },
+    };
+
+    write_palette(output, config, palette);
+    if let Some(index) = config.transparent_index {
+        output.push(Item::Constant(String::from("TRANSPARENT_INDEX"),
+            index as u64));
+    }
+    palette_size
+}
+
+// Converts a single image into a document with its arrays and constants,
+// using the shared palette and its size in bits if there is one. Returns the
+// document along with notes about the conversion.
+pub(crate) fn convert_image(config: &Config, input: &Input,
+    mut img: image::DynamicImage, shared: Option<(&[Rgb], u8)>)
+    -> Result<(Document, Vec<String>), Error> {
+    let mut notes: Vec<String> = Vec::new();
+    let (width, height) = img.to_bgra().dimensions();
+    let mut output = Document::new(width, height, &input.prefix);
+
     match config.no_palette {
         true => {
+            // Dither the image to the colours the colour format can represent
+            if let Some(method) = config.dither {
+                img = dither::reduce_depth(&img, method, &config.colour_format);
+            }
+
             // Add the image data array (colours instead of indices) to the
             // output string
-            write_raw_image_data(&mut output, &config, &img);
+            write_raw_image_data(&mut output, config, &img);
         }
         false => {
-            // Construct the palette for the conversion
-            let palette = construct_palette(&config, &img)?;
-
-            // Add the palette to the output string
-            write_palette(&mut output, &config, &palette);
+            // Construct the palette for the conversion and add it to the
+            // output, unless a shared palette is used
+            let (palette, palette_size) = match shared {
+                Some((palette, palette_size)) => {
+                    output.shared_palette_len = Some(palette.len());
+                    (palette.to_vec(), palette_size)
+                },
+                None => {
+                    let images = std::slice::from_mut(&mut img);
+                    notes.extend(reduce_colours(config, images));
+                    let palette = construct_palette(config, images)?;
+                    let palette_size = add_palette(&mut output, config,
+                        &palette, &mut notes);
+                    (palette, palette_size)
+                },
+            };
 
-            // If we have a separate palette file, check that the image doesn't
-            // have any colours not found in the palette
+            // If we have a separate palette file, either map the colours in
+            // the image to the nearest ones in the palette or check that the
+            // image doesn't have any colours not found in the palette
             if config.palette_path.is_some() {
-                check_against_palette(&img, &palette)?;
+                let opaque = opaque_colours(config, &palette);
+                match config.nearest {
+                    Some(metric) => {
+                        let (mapped, remapped) = nearest::map_to_palette(&img,
+                            &opaque, metric, config.dither,
+                            config.transparency());
+                        img = mapped;
+                        notes.push(format!("{} pixels mapped to the nearest \
+                            palette colour, worst error {:.2} ({:?})",
+                            remapped.pixels, remapped.worst_error, metric));
+                    },
+                    None => check_against_palette(config, &img, &opaque)?,
+                }
             }
 
             // Add the image data array to the output
-            write_image_data(&mut output, &config, &img, &palette);
+            write_image_data(&mut output, config, palette_size, &img,
+                &palette);
         }
     }
 
-    // Write output string to file
-    if let Err(e) = fs::write(&config.output_path, output) {
-        return Err(format!("Error writing output file: {}", e.to_string()))
+    // Add the mask of opaque pixels to the output
+    if config.mask {
+        write_mask(&mut output, config, &img);
     }
 
-    Ok(())
+    Ok((output, notes))
 }
 
-// Returns both a vector of all the colours for indexing purposes as well as a
-// hashset for the purpose of checking if some colours exist in the image.
-fn list_colours(palette_img: &image::DynamicImage) -> Vec<Rgb> {
+// Returns a vector of all the distinct colours in the images in the order they
+// appear, leaving out transparent pixels if an alpha threshold is given.
+// Colours that only differ by alpha are distinct if alpha is kept.
+fn list_colours(images: &[image::DynamicImage], alpha_threshold: Option<u8>,
+    keep_alpha: bool) -> Vec<Rgb> {
     let mut colours: HashSet<Rgb> = HashSet::new();
     let mut palette: Vec<Rgb> = Vec::new();
-    let palette_img = palette_img.to_bgra();
 
-    for pixel in palette_img.enumerate_pixels() {
-        let colour = Rgb::from(pixel.2);
-        if colours.insert(colour) {
-            palette.push(colour);
+    for img in images {
+        for pixel in img.to_bgra().pixels() {
+            if is_transparent(pixel, alpha_threshold) {
+                continue
+            }
+            let colour = Rgb::from_pixel(pixel, keep_alpha);
+            if colours.insert(colour) {
+                palette.push(colour);
+            }
         }
     }
     palette
 }
 
-fn construct_palette(config: &Config, img: &image::DynamicImage)
-    -> Result<Vec<Rgb>, String> {
-    match &config.palette_path {
+// Constructs the palette from the palette file, or from the colours in the
+// images if there isn't one
+fn construct_palette(config: &Config, images: &[image::DynamicImage])
+    -> Result<Vec<Rgb>, Error> {
+    let palette = match &config.palette_path {
         Some(path) => {
-            let palette_img = match image::open(&path) {
+            let palette_img = match image::open(path) {
                 Ok(img) => img,
-                Err(e) => return Err(format!("Error opening palette file \
-                    \"{}\": {}", &path, e.to_string())),
+                Err(error) => return Err(Error::PaletteOpen {
+                    path: path.clone(), error }),
             };
-            Ok(list_colours(&palette_img))
+            let palette = list_colours(std::slice::from_ref(&palette_img),
+                None, config.colour_format.has_alpha());
+            // The transparent index has to be one of the palette's colours
+            if let Some(index) = config.transparent_index {
+                if index >= palette.len() {
+                    return Err(Error::Argument(format!("Transparent index \
+                        {} is outside the palette of {} colours", index,
+                        palette.len())))
+                }
+            }
+            palette
         },
         None => {
-            let palette = list_colours(&img);
-            if palette.len() > match config.palette_size {
-                8 => 256,
-                16 => 65536,
-                32 => 4294967296,
-                _ => 0,
-            } {
-                return Err(format!("Image file has too many \
-                    colours for palette size of {}",
-                    config.palette_size))
+            let mut palette = list_colours(images, config.transparency(),
+                config.colour_format.has_alpha());
+            // Reserve the transparent index with a placeholder colour,
+            // padding the palette if the index is past its end
+            if let Some(index) = config.transparent_index {
+                while palette.len() < index {
+                    palette.push(Rgb(0));
+                }
+                palette.insert(index, Rgb(0));
             }
-            Ok(palette)
+            palette
         },
+    };
+
+    // Make sure every palette index fits in the chosen palette size
+    let palette_size = max_palette_size(config.palette_size);
+    if palette.len() as u64 > max_palette_len(palette_size) {
+        return Err(Error::TooManyColours {
+            palette_file: config.palette_path.is_some(),
+            images: images.len(),
+            colours: palette.len(),
+            palette_size,
+        })
     }
+    Ok(palette)
+}
+
+// Returns the largest palette size in bits that the config allows
+fn max_palette_size(palette_size: PaletteSize) -> u8 {
+    match palette_size {
+        PaletteSize::Bits(bits) => bits,
+        PaletteSize::Auto => *AUTO_PALETTE_SIZES.last().unwrap(),
+    }
+}
+
+// Palette sizes that can be chosen automatically, from smallest to largest
+const AUTO_PALETTE_SIZES: [u8; 5] = [1, 2, 4, 8, 16];
+
+// Returns the smallest automatically choosable palette size that can index
+// the given number of colours
+fn smallest_palette_size(colours: usize) -> u8 {
+    *AUTO_PALETTE_SIZES.iter()
+        .find( |&&bits| colours as u64 <= max_palette_len(bits) )
+        .unwrap_or(AUTO_PALETTE_SIZES.last().unwrap())
 }
 
-fn write_palette(output: &mut String, config: &Config, palette: &Vec<Rgb>) {
-    output.push_str("\nconst ");
-    match config.colour_format {
-        ColourFormat::RGB565 => output.push_str("uint16_t"),
-        _ => output.push_str("uint32_t"),
+// Returns the maximum number of colours that can be indexed with the given
+// palette size in bits
+fn max_palette_len(palette_size: u8) -> u64 {
+    match palette_size {
+        1 | 2 | 4 | 8 | 16 | 32 => 1 << palette_size,
+        _ => 0,
     }
-    output.push_str(format!(" palette[{}] PROGMEM = {{\n",
-        palette.len()).as_str());
+}
+
+// Returns the colour's value in the configured format and order
+fn encode_colour(config: &Config, colour: &Rgb) -> u32 {
+    config.colour_format.encode_ordered(colour, config.channel_order,
+        config.byte_order)
+}
+
+fn write_palette(output: &mut Document, config: &Config, palette: &[Rgb]) {
+    output.push(Item::Array(Array {
+        name: String::from("palette"),
+        element: config.colour_format.element(),
+        values: palette.iter()
+            .map( |colour| encode_colour(config, colour) )
+            .collect(),
+        hex_digits: Some(config.colour_format.hex_digits(config.byte_order)),
+        spaced: true,
+        comment: None,
+    }));
+}
+
+// Returns the palette without the colour at the transparent index, if there is
+// one, since opaque pixels can't use it
+fn opaque_colours(config: &Config, palette: &[Rgb]) -> Vec<Rgb> {
+    palette.iter().enumerate()
+        .filter( |(index, _)| Some(*index) != config.transparent_index )
+        .map( |(_, colour)| *colour )
+        .collect()
+}
+
+// Number of pixel positions reported for each colour missing from the palette
+const MISSING_POSITIONS: usize = 5;
 
-    let mut line = String::from("    ");
-    let mut to_add: String;
-    for colour in palette {
-        match config.colour_format {
-            ColourFormat::RGB565 => to_add = format!("{:#06X}, ",
-                Rgb565::from(colour).0),
-            ColourFormat::RGB => to_add = format!("{:#08X}, ", colour.0),
+// Checks that every opaque pixel's colour is in the palette, reporting every
+// colour that isn't along with where it's found. An image highlighting those
+// pixels is written too if the config has a path for one.
+fn check_against_palette(config: &Config, img: &image::DynamicImage,
+    palette: &[Rgb]) -> Result<(), Error> {
+    let colours: HashSet<Rgb> = palette.iter().copied().collect();
+    let img = img.to_bgra();
+    let mut missing: Vec<MissingColour> = Vec::new();
+    let mut indices: HashMap<Rgb, usize> = HashMap::new();
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if is_transparent(pixel, config.transparency()) {
+            continue
+        }
+        let colour = Rgb::from_pixel(pixel, config.colour_format.has_alpha());
+        if colours.contains(&colour) {
+            continue
         }
-        // Check if we need to push the current value to the next line
-        if line.len() + to_add.len() > 80 {
-            output.push_str(format!("{}\n", line).as_str());
-            line = String::from("    ");
+        let index = *indices.entry(colour).or_insert_with( || {
+            missing.push(MissingColour { colour: colour.0, pixels: 0,
+                positions: Vec::new() });
+            missing.len() - 1
+        });
+        missing[index].pixels += 1;
+        if missing[index].positions.len() < MISSING_POSITIONS {
+            missing[index].positions.push((x, y));
         }
-        line.push_str(to_add.as_str());
     }
-    if !line.trim().is_empty() {
-        output.push_str(line.as_str());
+    if missing.is_empty() {
+        return Ok(())
     }
-    output.push_str("\n};\n");
+
+    if let Some(path) = &config.diagnostic_path {
+        write_diagnostic(config, &img, &colours, path)?;
+    }
+    Err(Error::ColoursNotInPalette(missing))
+}
+
+// Writes a PNG of the image with the pixels whose colours are missing from
+// the palette in magenta and the rest dimmed to grey
+fn write_diagnostic(config: &Config,
+    img: &image::ImageBuffer<image::Bgra<u8>, Vec<u8>>, palette: &HashSet<Rgb>,
+    path: &str) -> Result<(), Error> {
+    let (width, height) = img.dimensions();
+    let highlighted = image::RgbaImage::from_fn(width, height, |x, y| {
+        let pixel = img.get_pixel(x, y);
+        if is_transparent(pixel, config.transparency()) {
+            return image::Rgba([0, 0, 0, 0])
+        }
+        let colour = Rgb::from_pixel(pixel, config.colour_format.has_alpha());
+        match palette.contains(&colour) {
+            true => {
+                let sum: u32 = pixel.0[..3].iter().map( |&c| u32::from(c) )
+                    .sum();
+                let grey = (64 + sum / 6) as u8;
+                image::Rgba([grey, grey, grey, 255])
+            },
+            false => image::Rgba([255, 0, 255, 255]),
+        }
+    });
+    highlighted.save_with_format(path, image::ImageFormat::PNG)
+        .map_err( |error| Error::Write { path: Some(String::from(path)),
+            error } )
+}
+
+// Maps pixels to their indices in the palette
+struct PaletteMap {
+    // Hashmap for the palette so we don't need to search the palette vector
+    // on every pixel of the image
+    indices: HashMap<Rgb, usize>,
+    // Transparent index and the alpha threshold for using it
+    transparent: Option<(usize, u8)>,
+    // Whether colours that only differ by alpha are distinct
+    keep_alpha: bool,
 }
 
-fn check_against_palette(img: &image::DynamicImage, palette: &Vec<Rgb>)
-    -> Result<(), String> {
-    let img_colours = list_colours(&img);
-    for colour in img_colours.iter() {
-        if palette.iter().position( |c| c == colour ).is_none() {
-            return Err(format!("Colour {:#08X} isn't present in the palette",
-                colour.0))
+impl PaletteMap {
+    fn new(config: &Config, palette: &[Rgb]) -> Self {
+        let mut indices: HashMap<Rgb, usize> = HashMap::new();
+        for (index, colour) in palette.iter().enumerate() {
+            if Some(index) != config.transparent_index {
+                indices.entry(*colour).or_insert(index);
+            }
+        }
+        PaletteMap {
+            indices,
+            transparent: config.transparent_index
+                .map( |index| (index, config.alpha_threshold) ),
+            keep_alpha: config.colour_format.has_alpha(),
+        }
+    }
+
+    fn index(&self, pixel: &image::Bgra<u8>) -> usize {
+        match self.transparent {
+            Some((index, threshold)) if pixel[3] < threshold => index,
+            _ => *self.indices.get(&Rgb::from_pixel(pixel, self.keep_alpha))
+                .unwrap(),
         }
     }
-    Ok(())
 }
 
-fn write_image_data(output: &mut String, config: &Config,
-    img: &image::DynamicImage, palette: &Vec<Rgb>) {
-    // Create a hashmap for the palette so we don't need to search the palette
-    // vector on every pixel of the image
-    let mut palette_map: HashMap<Rgb, usize> = HashMap::new();
-    for index in 0..palette.len() {
-        palette_map.insert(palette[index], index);
+fn write_image_data(output: &mut Document, config: &Config, palette_size: u8,
+    img: &image::DynamicImage, palette: &[Rgb]) {
+    let palette_map = PaletteMap::new(config, palette);
+    output.bits_per_pixel = palette_size;
+
+    // Palette sizes of less than 8 bits get packed into bytes
+    if palette_size < 8 {
+        write_packed_image_data(output, config, palette_size, img,
+            &palette_map);
+        return
     }
 
     let img = img.to_bgra();
-    output.push_str(format!("\nconst uint{}_t image_data[{}] PROGMEM = {{\n",
-        config.palette_size, img.dimensions().0 * img.dimensions().1).as_str());
-    let mut line = String::from("    ");
-    let mut to_add: String;
-    for pixel in img.enumerate_pixels() {
-        let palette_index = palette_map.get(&Rgb::from(pixel.2)).unwrap();
+    output.push(Item::Array(Array {
+        name: String::from("image_data"),
+        element: Element::with_bits(palette_size),
+        values: img.pixels()
+            .map( |pixel| palette_map.index(pixel) as u32 )
+            .collect(),
+        hex_digits: None,
+        spaced: false,
+        comment: None,
+    }));
+}
+
+fn write_packed_image_data(output: &mut Document, config: &Config,
+    palette_size: u8, img: &image::DynamicImage, palette_map: &PaletteMap) {
+    let img = img.to_bgra();
+    let (width, height) = img.dimensions();
+    let bytes = pack_pixels(config, &img, palette_size,
+        |pixel| palette_map.index(pixel) as u8);
 
-        to_add = format!("{},", palette_index);
-        // Check if we need to push the current value to the next line
-        if line.len() + to_add.len() > 80 {
-            output.push_str(format!("{}\n", line).as_str());
-            line = String::from("    ");
-        }
+    let comment = format!("{}x{} pixels, {} bits per pixel{}", width, height,
+        palette_size, match config.pad_rows {
+            true => ", rows padded to whole bytes",
+            false => "",
+        });
+    output.push(byte_array("image_data", &bytes, comment));
+}
 
-        line.push_str(to_add.as_str());
+fn write_mask(output: &mut Document, config: &Config,
+    img: &image::DynamicImage) {
+    let img = img.to_bgra();
+    let (width, height) = img.dimensions();
+    let bytes = pack_pixels(config, &img, 1,
+        |pixel| (pixel[3] >= config.alpha_threshold) as u8);
+
+    let comment = format!("{}x{} pixel mask, 1 for opaque pixels{}", width,
+        height, match config.pad_rows {
+            true => ", rows padded to whole bytes",
+            false => "",
+        });
+    output.push(byte_array("image_mask", &bytes, comment));
+}
+
+// Packs a value of the given number of bits for every pixel into bytes in the
+// configured bit order, padding rows to whole bytes if configured to
+fn pack_pixels<F>(config: &Config, img: &image::ImageBuffer<image::Bgra<u8>,
+    Vec<u8>>, bits: u8, value: F) -> Vec<u8>
+    where F: Fn(&image::Bgra<u8>) -> u8 {
+    let width = img.dimensions().0;
+    let bits = u32::from(bits);
+    let per_byte = 8 / bits;
+
+    // Flush the current byte when it's full (or at the end of every row if
+    // rows are padded)
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut current: u8 = 0;
+    let mut count: u32 = 0;
+    for (x, _, pixel) in img.enumerate_pixels() {
+        let shift = match config.bit_order {
+            BitOrder::MsbFirst => 8 - bits * (count + 1),
+            BitOrder::LsbFirst => bits * count,
+        };
+        current |= value(pixel) << shift;
+        count += 1;
+
+        if count == per_byte || (config.pad_rows && x == width - 1) {
+            bytes.push(current);
+            current = 0;
+            count = 0;
+        }
     }
-    if !line.trim().is_empty() {
-        output.push_str(format!("{}\n", line).as_str());
+    if count > 0 {
+        bytes.push(current);
     }
-    output.push_str("};\n");
+    bytes
 }
 
-fn write_raw_image_data(output: &mut String, config: &Config,
+fn byte_array(name: &str, bytes: &[u8], comment: String) -> Item {
+    Item::Array(Array {
+        name: String::from(name),
+        element: Element::U8,
+        values: bytes.iter().map( |&byte| u32::from(byte) ).collect(),
+        hex_digits: Some(2),
+        spaced: false,
+        comment: Some(comment),
+    })
+}
+
+fn write_raw_image_data(output: &mut Document, config: &Config,
     img: &image::DynamicImage) {
     let img = img.to_bgra();
-    output.push_str("\nconst uint");
-    match config.colour_format {
-        ColourFormat::RGB565 => output.push_str("16"),
-        ColourFormat::RGB => output.push_str("32"),
-    }
-    output.push_str(format!("_t image_data[{}] PROGMEM = {{\n",
-        img.dimensions().0 * img.dimensions().1).as_str());
-    let mut line = String::from("    ");
-    let mut to_add: String;
-    for pixel in img.enumerate_pixels() {
-        let colour = Rgb::from(pixel.2);
-        match config.colour_format {
-            ColourFormat::RGB565 => to_add = format!("{:#06X},",
-                Rgb565::from(&colour).0),
-            ColourFormat::RGB => to_add = format!("{:#08X},", colour.0),
-        }
-        // Check if we need to push the current value to the next line
-        if line.len() + to_add.len() > 80 {
-            output.push_str(format!("{}\n", line).as_str());
-            line = String::from("    ");
-        }
-
-        line.push_str(to_add.as_str());
-    }
-    if !line.trim().is_empty() {
-        output.push_str(format!("{}\n", line).as_str());
-    }
-    output.push_str("};\n");
+    // Values take up the whole element they're written as, even for formats
+    // with fewer bits
+    output.bits_per_pixel = (config.colour_format.element().bytes() * 8) as u8;
+    output.push(Item::Array(Array {
+        name: String::from("image_data"),
+        element: config.colour_format.element(),
+        values: img.pixels()
+            .map( |pixel| encode_colour(config, &Rgb::from_pixel(pixel,
+                config.colour_format.has_alpha())) )
+            .collect(),
+        hex_digits: Some(config.colour_format.hex_digits(config.byte_order)),
+        spaced: false,
+        comment: None,
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Palette indices of the 7x7 example heart
+    const HEART: [u8; 49] = [
+        0,2,2,0,2,2,0, 2,3,2,2,2,2,2, 2,2,2,2,2,2,2, 1,2,2,2,2,2,1,
+        0,1,2,2,2,1,0, 0,0,1,2,1,0,0, 0,0,0,1,0,0,0,
+    ];
+
+    // The heart as an image with the palette indices kept in its blue channel
+    fn heart() -> image::ImageBuffer<image::Bgra<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(7, 7, |x, y| {
+            image::Bgra([HEART[(y * 7 + x) as usize], 0, 0, 255])
+        })
+    }
+
+    #[test]
+    fn pack_heart() {
+        let mut config = Config::default();
+        let img = heart();
+        let pack = |config: &Config| pack_pixels(config, &img, 2,
+            |pixel| pixel[0] );
+        assert_eq!(pack(&config), vec![0x28, 0xA2, 0xEA, 0xAA, 0xAA, 0x9A,
+            0xA9, 0x1A, 0x90, 0x19, 0x00, 0x10, 0x00]);
+        config.bit_order = BitOrder::LsbFirst;
+        assert_eq!(pack(&config), vec![0x28, 0x8A, 0xAB, 0xAA, 0xAA, 0xA6,
+            0x6A, 0xA4, 0x06, 0x64, 0x00, 0x04, 0x00]);
+    }
+
+    #[test]
+    fn pack_and_unpack() {
+        let img = heart();
+        for &bits in &[1, 2, 4] {
+            let expected: Vec<u32> = HEART.iter()
+                .map( |&index| u32::from(index) & ((1 << bits) - 1) )
+                .collect();
+            for &lsb_first in &[false, true] {
+                for &pad_rows in &[false, true] {
+                    let config = Config {
+                        bit_order: match lsb_first {
+                            true => BitOrder::LsbFirst,
+                            false => BitOrder::MsbFirst,
+                        },
+                        pad_rows,
+                        ..Config::default()
+                    };
+                    let bytes: Vec<u32> = pack_pixels(&config, &img, bits,
+                        |pixel| pixel[0] & ((1 << bits) - 1) )
+                        .into_iter().map(u32::from).collect();
+
+                    let row_bytes = (7 * bits as usize).div_ceil(8);
+                    let len = match pad_rows {
+                        true => row_bytes * 7,
+                        false => (49 * bits as usize).div_ceil(8),
+                    };
+                    let case = format!("{} bits, LSB first: {}, padded: {}",
+                        bits, lsb_first, pad_rows);
+                    assert_eq!(bytes.len(), len, "{}", case);
+                    assert_eq!(preview::unpack(&config, &bytes, bits, 7, 7),
+                        expected, "{}", case);
+                }
+            }
+        }
+    }
 }
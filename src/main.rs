@@ -1,15 +1,39 @@
 use std::env;
 use std::process;
+use img_to_array::Error;
 
 fn main() {
-    // Parse command line arguments into a config
-    let config = img_to_array::parse_config(env::args().collect())
-        .unwrap_or_else( |_| { process::exit(1); });
+    // Parse command line arguments into configs
+    let args: Vec<String> = env::args().collect();
+    let configs = img_to_array::parse_config(args.clone())
+        .unwrap_or_else( |e| {
+            // Show the usage along with mistakes in the options
+            match e {
+                Error::Help => (),
+                _ => eprintln!("{}", e),
+            }
+            if let Error::Help | Error::Argument(_) = e {
+                print!("{}", img_to_array::usage(&args[0]));
+            }
+            process::exit(1);
+        });
 
-    // Convert the file
-    if let Err(e) = img_to_array::convert(&config) {
-        println!("{}", e);
-        process::exit(1);
-    };
-    println!("Arrays written successfully to file \"{}\"", config.output_path);
+    // Convert the files
+    for config in configs {
+        let notes = img_to_array::convert(&config).unwrap_or_else( |e| {
+            println!("{}", e);
+            process::exit(1);
+        });
+        for note in notes {
+            println!("{}", note);
+        }
+        for path in config.output_paths() {
+            match config.reverse {
+                true => println!("Image written successfully to file \"{}\"",
+                    path),
+                false => println!("Arrays written successfully to file \
+                    \"{}\"", path),
+            }
+        }
+    }
 }
@@ -0,0 +1,681 @@
+// Manifests list assets to convert in one run, each with the same options as
+// the command line. They can be written in a subset of TOML:
+//
+//     # Options for every asset
+//     palsize = 4
+//
+//     [[asset]]
+//     image = "heart.png"
+//     name = "heart"
+//     mask = true
+//
+// or in JSON, with the assets in an "assets" array:
+//
+//     { "palsize": 4, "assets": [ { "image": "heart.png", "mask": true } ] }
+
+// A value given to a key
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+// A key and its value, along with the line it's on
+#[derive(Debug, Clone)]
+pub(crate) struct Field {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+// An asset along with the line it starts on
+#[derive(Debug)]
+pub(crate) struct Asset {
+    pub line: usize,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Manifest {
+    // Fields used for every asset unless the asset has its own
+    pub defaults: Vec<Field>,
+    pub assets: Vec<Asset>,
+}
+
+impl Manifest {
+    // Returns the fields used for the asset: its own, followed by the
+    // defaults it doesn't override
+    pub(crate) fn fields<'a>(&'a self, asset: &'a Asset) -> Vec<&'a Field> {
+        asset.fields.iter()
+            .chain(self.defaults.iter()
+                .filter( |d| asset.fields.iter().all( |f| f.key != d.key ) ))
+            .collect()
+    }
+}
+
+// Parses the text of a manifest file, as JSON or TOML. Errors are returned
+// with the line they're on.
+pub(crate) fn parse(text: &str, json: bool)
+    -> Result<Manifest, (usize, String)> {
+    let manifest = match json {
+        true => JsonParser { chars: text.chars().collect(), pos: 0, line: 1 }
+            .manifest()?,
+        false => parse_toml(text)?,
+    };
+
+    // Keys can only be given once per asset
+    for fields in std::iter::once(&manifest.defaults)
+        .chain(manifest.assets.iter().map( |asset| &asset.fields )) {
+        for (i, field) in fields.iter().enumerate() {
+            if fields[..i].iter().any( |f| f.key == field.key ) {
+                return Err((field.line, format!("Key {} given more than once",
+                    field.key)))
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+fn parse_toml(text: &str) -> Result<Manifest, (usize, String)> {
+    let mut manifest = Manifest { defaults: Vec::new(), assets: Vec::new() };
+    let lines: Vec<Vec<char>> = text.lines()
+        .map( |line| line.chars().collect() ).collect();
+    let mut row = 0;
+    while row < lines.len() {
+        let number = row + 1;
+        let chars = &lines[row];
+        let mut pos = 0;
+        skip_spaces(chars, &mut pos);
+        if pos == chars.len() || chars[pos] == '#' {
+            row += 1;
+            continue
+        }
+
+        // Every [[asset]] table starts a new asset
+        if chars[pos] == '[' {
+            let header: String = chars[pos..].iter().collect();
+            let header = match header.find('#') {
+                Some(comment) => &header[..comment],
+                None => header.as_str(),
+            };
+            match header.trim() {
+                "[[asset]]" | "[[assets]]" => manifest.assets.push(Asset {
+                    line: number, fields: Vec::new() }),
+                other => return Err((number, format!("Unknown table {}",
+                    other))),
+            }
+            row += 1;
+            continue
+        }
+
+        let key = match chars[pos] {
+            '"' | '\'' => toml_string(chars, &mut pos, number)?,
+            _ => {
+                let start = pos;
+                while pos < chars.len() && (chars[pos].is_ascii_alphanumeric()
+                    || chars[pos] == '_' || chars[pos] == '-') {
+                    pos += 1;
+                }
+                chars[start..pos].iter().collect()
+            },
+        };
+        skip_spaces(chars, &mut pos);
+        if chars.get(pos) == Some(&'.') {
+            return Err((number, String::from("Dotted keys aren't supported")))
+        }
+        if key.is_empty() || chars.get(pos) != Some(&'=') {
+            return Err((number, String::from("Expected key = value")))
+        }
+        pos += 1;
+        skip_spaces(chars, &mut pos);
+        // Arrays can go on over several lines
+        let value = toml_value(&lines, &mut row, &mut pos)?;
+
+        // Only a comment can follow the value
+        let chars = &lines[row];
+        skip_spaces(chars, &mut pos);
+        if let Some(c) = chars.get(pos) {
+            if *c != '#' {
+                return Err((row + 1, format!("Unexpected text after value \
+                    of {}", key)))
+            }
+        }
+
+        let field = Field { key, value, line: number };
+        match manifest.assets.last_mut() {
+            Some(asset) => asset.fields.push(field),
+            None => manifest.defaults.push(field),
+        }
+        row += 1;
+    }
+    Ok(manifest)
+}
+
+fn skip_spaces(chars: &[char], pos: &mut usize) {
+    while *pos < chars.len() && (chars[*pos] == ' ' || chars[*pos] == '\t') {
+        *pos += 1;
+    }
+}
+
+// Skips spaces, comments and line breaks inside an array, moving on to the
+// next line at the end of one
+fn skip_array_space(lines: &[Vec<char>], row: &mut usize, pos: &mut usize)
+    -> Result<(), (usize, String)> {
+    loop {
+        skip_spaces(&lines[*row], pos);
+        match lines[*row].get(*pos) {
+            None | Some('#') => {
+                if *row + 1 == lines.len() {
+                    return Err((*row + 1, String::from("Unterminated array")))
+                }
+                *row += 1;
+                *pos = 0;
+            },
+            Some(_) => return Ok(()),
+        }
+    }
+}
+
+// Parses the value at the position, which can only go past the end of the
+// line for arrays. Errors are returned with the line they're on.
+fn toml_value(lines: &[Vec<char>], row: &mut usize, pos: &mut usize)
+    -> Result<Value, (usize, String)> {
+    let chars = &lines[*row];
+    let line = *row + 1;
+    match chars.get(*pos) {
+        Some('"') | Some('\'') => {
+            if chars[*pos..].starts_with(&[chars[*pos]; 3]) {
+                return Err((line, String::from("Multi-line strings aren't \
+                    supported")))
+            }
+            Ok(Value::String(toml_string(chars, pos, line)?))
+        },
+        Some('{') => Err((line, String::from("Inline tables aren't \
+            supported"))),
+        Some('[') => {
+            *pos += 1;
+            let mut values: Vec<Value> = Vec::new();
+            loop {
+                skip_array_space(lines, row, pos)?;
+                if lines[*row].get(*pos) == Some(&']') {
+                    *pos += 1;
+                    return Ok(Value::Array(values))
+                }
+                values.push(toml_value(lines, row, pos)?);
+                skip_array_space(lines, row, pos)?;
+                match lines[*row].get(*pos) {
+                    Some(',') => *pos += 1,
+                    Some(']') => (),
+                    _ => return Err((*row + 1, String::from("Expected , or ] \
+                        in array"))),
+                }
+            }
+        },
+        _ => {
+            let start = *pos;
+            while *pos < chars.len() && (chars[*pos].is_ascii_alphanumeric()
+                || chars[*pos] == '_' || chars[*pos] == '-'
+                || chars[*pos] == '+' || chars[*pos] == '.') {
+                *pos += 1;
+            }
+            let word: String = chars[start..*pos].iter().collect();
+            match word.as_str() {
+                "" => Err((line, String::from("Expected a value"))),
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => match word.replace('_', "").parse::<i64>() {
+                    Ok(integer) => Ok(Value::Integer(integer)),
+                    Err(_) if word.contains('.') => Err((line, format!(
+                        "Invalid value {} (only whole numbers are \
+                        supported)", word))),
+                    Err(_) => Err((line, format!("Invalid value {}", word))),
+                },
+            }
+        },
+    }
+}
+
+// Parses a basic ("...") or literal ('...') string, only the first of which
+// has escapes
+fn toml_string(chars: &[char], pos: &mut usize, line: usize)
+    -> Result<String, (usize, String)> {
+    let quote = chars[*pos];
+    *pos += 1;
+    let mut string = String::new();
+    while let Some(&c) = chars.get(*pos) {
+        *pos += 1;
+        match c {
+            c if c == quote => return Ok(string),
+            '\\' if quote == '"' => {
+                string.push(escape(chars, pos, line)?);
+            },
+            c => string.push(c),
+        }
+    }
+    Err((line, String::from("Unterminated string")))
+}
+
+// Returns the character the escape sequence after a backslash stands for,
+// moving past the sequence. Characters outside the Basic Multilingual Plane
+// are given as a UTF-16 surrogate pair of \u escapes in JSON and with a \U
+// escape of 8 digits in TOML.
+fn escape(chars: &[char], pos: &mut usize, line: usize)
+    -> Result<char, (usize, String)> {
+    let c = chars.get(*pos).copied();
+    *pos += 1;
+    match c {
+        Some('"') => Ok('"'),
+        Some('\\') => Ok('\\'),
+        Some('/') => Ok('/'),
+        Some('b') => Ok('\u{8}'),
+        Some('f') => Ok('\u{c}'),
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('r') => Ok('\r'),
+        Some('u') => {
+            let mut code = hex_escape(chars, pos, line, 4)?;
+            let low = chars.get(*pos..*pos + 2) == Some(&['\\', 'u'][..]);
+            if (0xD800..0xDC00).contains(&code) && low {
+                *pos += 2;
+                let low = hex_escape(chars, pos, line, 4)?;
+                if (0xDC00..0xE000).contains(&low) {
+                    code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                }
+            }
+            char::from_u32(code).ok_or_else( || (line, format!("Invalid \
+                unicode escape sequence \\u{:04X}", code)) )
+        },
+        Some('U') => {
+            let code = hex_escape(chars, pos, line, 8)?;
+            char::from_u32(code).ok_or_else( || (line, format!("Invalid \
+                unicode escape sequence \\U{:08X}", code)) )
+        },
+        Some(c) => Err((line, format!("Unknown escape sequence \\{}", c))),
+        None => Err((line, String::from("Unterminated string"))),
+    }
+}
+
+// Parses the given number of hex digits of a unicode escape sequence, moving
+// past them
+fn hex_escape(chars: &[char], pos: &mut usize, line: usize, digits: usize)
+    -> Result<u32, (usize, String)> {
+    let hex: String = chars.iter().skip(*pos).take(digits).collect();
+    match hex.len() == digits && hex.chars().all( |c| c.is_ascii_hexdigit() ) {
+        true => {
+            *pos += digits;
+            Ok(u32::from_str_radix(&hex, 16).unwrap_or_default())
+        },
+        false => Err((line, format!("Unicode escape sequences need {} hex \
+            digits", digits))),
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl JsonParser {
+    fn manifest(&mut self) -> Result<Manifest, (usize, String)> {
+        let mut manifest = Manifest { defaults: Vec::new(),
+            assets: Vec::new() };
+        let (_, fields) = self.object( |parser, key, _| match key {
+            "assets" | "asset" => {
+                parser.expect('[')?;
+                let mut assets: Vec<Asset> = Vec::new();
+                if !parser.next_is(']')? {
+                    loop {
+                        let (line, fields) = parser.object( |parser, _, _|
+                            parser.value().map(Some) )?;
+                        assets.push(Asset { line, fields });
+                        if !parser.separator(']')? {
+                            break
+                        }
+                    }
+                }
+                manifest.assets = assets;
+                Ok(None)
+            },
+            _ => parser.value().map(Some),
+        })?;
+        manifest.defaults = fields;
+
+        self.skip_whitespace();
+        match self.pos < self.chars.len() {
+            true => Err((self.line, String::from("Unexpected text after the \
+                manifest"))),
+            false => Ok(manifest),
+        }
+    }
+
+    // Parses an object, getting the value of every key from the given
+    // function, which can also consume the value itself and return None.
+    // Returns the line the object starts on and its fields.
+    fn object<F>(&mut self, mut value: F)
+        -> Result<(usize, Vec<Field>), (usize, String)>
+        where F: FnMut(&mut Self, &str, usize)
+            -> Result<Option<Value>, (usize, String)> {
+        self.expect('{')?;
+        let start = self.line;
+        let mut fields: Vec<Field> = Vec::new();
+        if self.next_is('}')? {
+            return Ok((start, fields))
+        }
+        loop {
+            self.skip_whitespace();
+            let line = self.line;
+            let key = self.string()?;
+            self.expect(':')?;
+            if let Some(v) = value(self, &key, line)? {
+                fields.push(Field { key, value: v, line });
+            }
+            if !self.separator('}')? {
+                return Ok((start, fields))
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, (usize, String)> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('[') => {
+                self.pos += 1;
+                let mut values: Vec<Value> = Vec::new();
+                if !self.next_is(']')? {
+                    loop {
+                        values.push(self.value()?);
+                        if !self.separator(']')? {
+                            break
+                        }
+                    }
+                }
+                Ok(Value::Array(values))
+            },
+            Some('{') => Err((self.line, String::from("Unexpected object"))),
+            _ => {
+                let start = self.pos;
+                while self.pos < self.chars.len()
+                    && (self.chars[self.pos].is_ascii_alphanumeric()
+                    || self.chars[self.pos] == '-'
+                    || self.chars[self.pos] == '.') {
+                    self.pos += 1;
+                }
+                let word: String = self.chars[start..self.pos].iter()
+                    .collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => match word.parse::<i64>() {
+                        Ok(integer) => Ok(Value::Integer(integer)),
+                        Err(_) if word.starts_with( |c: char|
+                            c.is_ascii_digit() || c == '-' )
+                            && word.contains(['.', 'e', 'E']) => Err((
+                            self.line, format!("Invalid value {} (only whole \
+                            numbers are supported)", word))),
+                        Err(_) => Err((self.line, format!("Invalid value {}",
+                            word))),
+                    },
+                }
+            },
+        }
+    }
+
+    fn string(&mut self) -> Result<String, (usize, String)> {
+        self.expect('"')?;
+        let mut string = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    string.push(escape(&self.chars, &mut self.pos,
+                        self.line)?);
+                },
+                '\n' => break,
+                c => string.push(c),
+            }
+        }
+        Err((self.line, String::from("Unterminated string")))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.get(self.pos) {
+            if !c.is_whitespace() {
+                break
+            }
+            if c == '\n' {
+                self.line += 1;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), (usize, String)> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some(&c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            },
+            Some(&c) => Err((self.line, format!("Expected {} but found {}",
+                expected, c))),
+            None => Err((self.line, format!("Expected {} but the file ended",
+                expected))),
+        }
+    }
+
+    // Consumes the closing character if it's next
+    fn next_is(&mut self, close: char) -> Result<bool, (usize, String)> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some(&c) if c == close => {
+                self.pos += 1;
+                Ok(true)
+            },
+            Some(_) => Ok(false),
+            None => Err((self.line, format!("Expected {} but the file ended",
+                close))),
+        }
+    }
+
+    // Consumes either a comma, returning true, or the closing character,
+    // returning false
+    fn separator(&mut self, close: char) -> Result<bool, (usize, String)> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some(',') => {
+                self.pos += 1;
+                Ok(true)
+            },
+            Some(&c) if c == close => {
+                self.pos += 1;
+                Ok(false)
+            },
+            _ => Err((self.line, format!("Expected , or {}", close))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the keys and values of the fields as strings for comparing
+    fn pairs(fields: &[&Field]) -> Vec<(String, String)> {
+        fields.iter()
+            .map( |f| (f.key.clone(), format!("{:?}", f.value)) )
+            .collect()
+    }
+
+    fn error(text: &str, json: bool) -> (usize, String) {
+        parse(text, json).expect_err("manifest should be rejected")
+    }
+
+    #[test]
+    fn asset_fields_override_defaults() {
+        let manifest = parse("palsize = 4\nmask = true\n\n[[asset]]\n\
+            image = \"a.png\"\npalsize = 2\n\n[[asset]]\nimage = \"b.png\"\n",
+            false).unwrap();
+        assert_eq!(manifest.defaults.len(), 2);
+        assert_eq!(manifest.assets.len(), 2);
+        assert_eq!(manifest.assets[0].line, 4);
+        assert_eq!(manifest.assets[1].line, 8);
+
+        let first = manifest.fields(&manifest.assets[0]);
+        assert_eq!(pairs(&first), vec![
+            (String::from("image"), String::from("String(\"a.png\")")),
+            (String::from("palsize"), String::from("Integer(2)")),
+            (String::from("mask"), String::from("Bool(true)")),
+        ]);
+        let second = manifest.fields(&manifest.assets[1]);
+        assert_eq!(pairs(&second), vec![
+            (String::from("image"), String::from("String(\"b.png\")")),
+            (String::from("palsize"), String::from("Integer(4)")),
+            (String::from("mask"), String::from("Bool(true)")),
+        ]);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        assert_eq!(error("[[asset]]\nmask = true\nmask = false\n", false),
+            (3, String::from("Key mask given more than once")));
+        assert_eq!(error("palsize = 1\n'palsize' = 2\n", false),
+            (2, String::from("Key palsize given more than once")));
+        assert_eq!(error("{ \"assets\": [ { \"mask\": true,\n\
+            \"mask\": true } ] }", true),
+            (2, String::from("Key mask given more than once")));
+        // The same key in the defaults and an asset is an override
+        assert!(parse("mask = true\n[[asset]]\nmask = false\n", false)
+            .is_ok());
+    }
+
+    #[test]
+    fn string_escapes() {
+        let manifest = parse("a = \"x\\\"y\\\\z\\n\\t\"\nb = 'C:\\dir\\n'\n",
+            false).unwrap();
+        assert_eq!(pairs(&manifest.defaults.iter().collect::<Vec<_>>()), vec![
+            (String::from("a"), String::from("String(\"x\\\"y\\\\z\\n\\t\")")),
+            (String::from("b"), String::from("String(\"C:\\\\dir\\\\n\")")),
+        ]);
+        let manifest = parse("{ \"a\": \"x\\/y\\\"\", \"assets\": [] }", true)
+            .unwrap();
+        assert_eq!(format!("{:?}", manifest.defaults[0].value),
+            "String(\"x/y\\\"\")");
+        assert_eq!(error("a = \"\\q\"\n", false),
+            (1, String::from("Unknown escape sequence \\q")));
+        assert_eq!(error("a = \"open\n", false),
+            (1, String::from("Unterminated string")));
+    }
+
+    // Returns the string value of the first default in the manifest
+    fn string(text: &str, json: bool) -> String {
+        match &parse(text, json).unwrap().defaults[0].value {
+            Value::String(string) => string.clone(),
+            value => panic!("{:?} isn't a string", value),
+        }
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        assert_eq!(string(r#"{ "a": "\u0041\u00e9\b\f", "assets": [] }"#,
+            true), "A\u{e9}\u{8}\u{c}");
+        assert_eq!(string(r#"{ "a": "\ud83d\ude00.png", "assets": [] }"#,
+            true), "\u{1F600}.png");
+        assert_eq!(string(r#"a = "\u00E9\U0001F600\b\f""#, false),
+            "\u{e9}\u{1F600}\u{8}\u{c}");
+        // Literal strings don't have escapes
+        assert_eq!(string(r#"a = '\u00E9'"#, false), "\\u00E9");
+
+        assert_eq!(error(r#"{ "a": "\u00G1" }"#, true), (1, String::from(
+            "Unicode escape sequences need 4 hex digits")));
+        assert_eq!(error(r#"a = "\U0001F6""#, false), (1, String::from(
+            "Unicode escape sequences need 8 hex digits")));
+        assert_eq!(error(r#"{ "a": "\ud83d.png" }"#, true), (1, String::from(
+            "Invalid unicode escape sequence \\uD83D")));
+        assert_eq!(error(r#"a = "\U00110000""#, false), (1, String::from(
+            "Invalid unicode escape sequence \\U00110000")));
+    }
+
+    #[test]
+    fn arrays() {
+        let manifest = parse("images = [\"a.png\", 'b.png',]\n\
+            sizes = [ 1, 2 ]\nempty = []\n", false).unwrap();
+        assert_eq!(format!("{:?}", manifest.defaults[0].value),
+            "Array([String(\"a.png\"), String(\"b.png\")])");
+        assert_eq!(format!("{:?}", manifest.defaults[1].value),
+            "Array([Integer(1), Integer(2)])");
+        assert_eq!(format!("{:?}", manifest.defaults[2].value), "Array([])");
+
+        // Arrays can go on over several lines, with comments in between
+        let manifest = parse("images = [\n    \"a.png\", # first\n\n\
+            # second\n    \"b.png\"\n]\nmask = true\n", false).unwrap();
+        assert_eq!(format!("{:?}", manifest.defaults[0].value),
+            "Array([String(\"a.png\"), String(\"b.png\")])");
+        assert_eq!(manifest.defaults[1].line, 7);
+
+        assert_eq!(error("images = [\n\"a.png\"\n", false),
+            (2, String::from("Unterminated array")));
+        assert_eq!(error("images = [\"a.png\" \"b.png\"]\n", false),
+            (1, String::from("Expected , or ] in array")));
+        assert_eq!(error("{ \"images\": [1, 2 }", true),
+            (1, String::from("Expected , or ]")));
+    }
+
+    #[test]
+    fn text_after_value() {
+        assert_eq!(error("palsize = 4 5\n", false),
+            (1, String::from("Unexpected text after value of palsize")));
+        assert_eq!(error("images = [\n\"a.png\"\n] x\n", false),
+            (3, String::from("Unexpected text after value of images")));
+        assert!(parse("palsize = 4 # comment\n", false).is_ok());
+        assert_eq!(error("{ \"assets\": [] } x", true),
+            (1, String::from("Unexpected text after the manifest")));
+    }
+
+    #[test]
+    fn error_lines() {
+        assert_eq!(error("# comment\n\npalsize = 4\nmask = \n", false),
+            (4, String::from("Expected a value")));
+        assert_eq!(error("\n[asset]\n", false),
+            (2, String::from("Unknown table [asset]")));
+        assert_eq!(error("mask true\n", false),
+            (1, String::from("Expected key = value")));
+        assert_eq!(error("a.b = 1\n", false),
+            (1, String::from("Dotted keys aren't supported")));
+        assert_eq!(error("alpha = 1.5\n", false), (1, String::from(
+            "Invalid value 1.5 (only whole numbers are supported)")));
+        assert_eq!(error("{\n  \"palsize\": 4,\n  \"alpha\": 1.5\n}", true),
+            (3, String::from(
+                "Invalid value 1.5 (only whole numbers are supported)")));
+        assert_eq!(error("{\n  \"palsize\": 4\n", true),
+            (3, String::from("Expected , or }")));
+    }
+
+    #[test]
+    fn json_assets() {
+        let manifest = parse("{\n  \"palsize\": 4,\n  \"assets\": [\n    \
+            { \"image\": \"a.png\", \"mask\": true },\n    \
+            { \"images\": [\"b.png\", \"c.png\"], \"palsize\": -1 }\n  ]\n}",
+            true).unwrap();
+        assert_eq!(manifest.defaults.len(), 1);
+        assert_eq!(manifest.assets.len(), 2);
+        assert_eq!(manifest.assets[0].line, 4);
+        assert_eq!(manifest.assets[1].line, 5);
+
+        let second = manifest.fields(&manifest.assets[1]);
+        assert_eq!(pairs(&second), vec![
+            (String::from("images"),
+                String::from("Array([String(\"b.png\"), String(\"c.png\")])")),
+            (String::from("palsize"), String::from("Integer(-1)")),
+        ]);
+        assert_eq!(second[1].line, 5);
+
+        assert_eq!(error("{ \"assets\": [ { \"a\": { } } ] }", true),
+            (1, String::from("Unexpected object")));
+        assert!(parse("{ \"asset\": [] }", true).unwrap().assets.is_empty());
+    }
+}
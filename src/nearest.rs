@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use crate::colour::{ Rgb, is_transparent };
+use crate::dither::{ self, Dither };
+
+// How the difference between two colours is measured when looking for the
+// nearest colour in a palette
+#[derive(Debug, Clone, Copy)]
+pub enum ColourMetric {
+    // Euclidean distance in RGB space
+    Rgb,
+    // Euclidean distance in RGB space weighted by how sensitive the eye is to
+    // each channel ("redmean" approximation)
+    Weighted,
+    // CIE76 delta E, the euclidean distance in CIELAB space
+    Lab,
+}
+
+// Summary of the pixels changed when mapping an image to a palette
+pub struct Remapped {
+    pub pixels: usize,
+    pub worst_error: f32,
+}
+
+// Replaces every pixel in the image with the nearest colour in the palette
+// according to the given metric, dithering with the given method if there is
+// one. Transparent pixels (if an alpha threshold is given) are left as they
+// are. Returns the new image along with how many pixels were changed and by
+// how much at most.
+pub fn map_to_palette(img: &image::DynamicImage, palette: &[Rgb],
+    metric: ColourMetric, dither: Option<Dither>, alpha_threshold: Option<u8>)
+    -> (image::DynamicImage, Remapped) {
+    let mut cache: HashMap<Rgb, Rgb> = HashMap::new();
+    let mut nearest = |colour: Rgb| *cache.entry(colour)
+        .or_insert_with( || nearest_colour(colour, palette, metric) );
+
+    let mapped = match dither {
+        Some(method) => {
+            let spread = 256.0 / (palette.len().max(1) as f32).cbrt();
+            dither::dither(img, method, [spread; 3], alpha_threshold, nearest)
+        },
+        None => {
+            let mut mapped = img.to_bgra();
+            for pixel in mapped.pixels_mut() {
+                if is_transparent(pixel, alpha_threshold) {
+                    continue
+                }
+                let colour = nearest(Rgb::from(&*pixel));
+                pixel[0] = colour.b();
+                pixel[1] = colour.g();
+                pixel[2] = colour.r();
+            }
+            image::DynamicImage::ImageBgra8(mapped)
+        },
+    };
+
+    // Compare the original and mapped images to see what changed
+    let mut remapped = Remapped { pixels: 0, worst_error: 0.0 };
+    let (original, mapped_bgra) = (img.to_bgra(), mapped.to_bgra());
+    for (before, after) in original.pixels().zip(mapped_bgra.pixels()) {
+        let (before, after) = (Rgb::from(before), Rgb::from(after));
+        if before != after {
+            remapped.pixels += 1;
+            remapped.worst_error = remapped.worst_error
+                .max(distance(before, after, metric));
+        }
+    }
+    (mapped, remapped)
+}
+
+// Returns the colour in the palette nearest to the given one
+pub fn nearest_colour(colour: Rgb, palette: &[Rgb], metric: ColourMetric)
+    -> Rgb {
+    *palette.iter()
+        .min_by( |a, b| distance(colour, **a, metric)
+            .partial_cmp(&distance(colour, **b, metric)).unwrap() )
+        .unwrap_or(&colour)
+}
+
+// Returns the difference between two colours according to the metric
+pub fn distance(a: Rgb, b: Rgb, metric: ColourMetric) -> f32 {
+    match metric {
+        ColourMetric::Rgb => {
+            let dr = f32::from(a.r()) - f32::from(b.r());
+            let dg = f32::from(a.g()) - f32::from(b.g());
+            let db = f32::from(a.b()) - f32::from(b.b());
+            (dr * dr + dg * dg + db * db).sqrt()
+        },
+        ColourMetric::Weighted => {
+            let mean_r = (f32::from(a.r()) + f32::from(b.r())) / 2.0;
+            let dr = f32::from(a.r()) - f32::from(b.r());
+            let dg = f32::from(a.g()) - f32::from(b.g());
+            let db = f32::from(a.b()) - f32::from(b.b());
+            ((2.0 + mean_r / 256.0) * dr * dr + 4.0 * dg * dg
+                + (2.0 + (255.0 - mean_r) / 256.0) * db * db).sqrt()
+        },
+        ColourMetric::Lab => {
+            let (a, b) = (to_lab(a), to_lab(b));
+            ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)
+                + (a[2] - b[2]).powi(2)).sqrt()
+        },
+    }
+}
+
+// Converts an sRGB colour to CIELAB (D65 white point)
+fn to_lab(colour: Rgb) -> [f32; 3] {
+    let linear = |v: u8| {
+        let v = f32::from(v) / 255.0;
+        if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+    };
+    let (r, g, b) = (linear(colour.r()), linear(colour.g()),
+        linear(colour.b()));
+
+    // Linear RGB to XYZ, relative to the D65 white point
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.950_47;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.088_83;
+
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
@@ -0,0 +1,524 @@
+use std::path::Path;
+use crate::Config;
+
+// Language the generated arrays are written in, or raw binary data
+#[derive(Debug, Clone, Copy)]
+pub enum Language { C, Rust, Binary }
+
+// Byte order of values in binary output
+#[derive(Debug, Clone, Copy)]
+pub enum Endianness { Little, Big }
+
+// Where arrays in C output are placed in memory, as the attribute or
+// qualifier added to their declarations
+#[derive(Debug, Clone)]
+pub enum Placement {
+    // AVR program memory through avr/pgmspace.h
+    Progmem,
+    // AVR named address space
+    Flash,
+    // ESP32 data RAM
+    DramAttr,
+    // ESP8266/ESP32 read-only data in flash
+    IcacheRodataAttr,
+    // GCC section attribute with the given section name
+    Section(String),
+    // Plain const without any attribute
+    Plain,
+    // Attribute written as given
+    Custom(String),
+}
+
+impl Placement {
+    // Returns the qualifier written between const and the element type
+    fn qualifier(&self) -> Option<&str> {
+        match self {
+            Placement::Flash => Some("__flash"),
+            _ => None,
+        }
+    }
+
+    // Returns the attribute written after the array name
+    fn attribute(&self) -> Option<String> {
+        match self {
+            Placement::Progmem => Some(String::from("PROGMEM")),
+            Placement::DramAttr => Some(String::from("DRAM_ATTR")),
+            Placement::IcacheRodataAttr =>
+                Some(String::from("ICACHE_RODATA_ATTR")),
+            Placement::Section(name) =>
+                Some(format!("__attribute__((section(\"{}\")))", name)),
+            Placement::Custom(attribute) => Some(attribute.clone()),
+            Placement::Flash | Placement::Plain => None,
+        }
+    }
+}
+
+// Identifies binary files written with a header
+const BINARY_MAGIC: &[u8; 4] = b"IMGA";
+
+// Type of the elements in an array
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Element { U8, U16, U32 }
+
+impl Element {
+    // Returns the smallest element type with at least the given number of
+    // bits
+    pub(crate) fn with_bits(bits: u8) -> Self {
+        match bits {
+            0..=8 => Element::U8,
+            9..=16 => Element::U16,
+            _ => Element::U32,
+        }
+    }
+
+    pub(crate) fn c_type(self) -> &'static str {
+        match self {
+            Element::U8 => "uint8_t",
+            Element::U16 => "uint16_t",
+            Element::U32 => "uint32_t",
+        }
+    }
+
+    // Returns the element type written as the given C type, if any
+    pub(crate) fn from_c_type(c_type: &str) -> Option<Self> {
+        match c_type {
+            "uint8_t" => Some(Element::U8),
+            "uint16_t" => Some(Element::U16),
+            "uint32_t" => Some(Element::U32),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn bytes(self) -> usize {
+        match self {
+            Element::U8 => 1,
+            Element::U16 => 2,
+            Element::U32 => 4,
+        }
+    }
+
+    fn rust_type(self) -> &'static str {
+        match self {
+            Element::U8 => "u8",
+            Element::U16 => "u16",
+            Element::U32 => "u32",
+        }
+    }
+}
+
+// An array of values along with how to write them
+pub(crate) struct Array {
+    // Name of the array in lowercase
+    pub name: String,
+    pub element: Element,
+    pub values: Vec<u32>,
+    // Number of hex digits to write values with, or None for decimal
+    pub hex_digits: Option<usize>,
+    // Whether values are separated by a space in addition to a comma
+    pub spaced: bool,
+    // Comment written right above the array
+    pub comment: Option<String>,
+}
+
+// Everything written to the output file, in order
+pub(crate) enum Item {
+    Comment(String),
+    // Constant with its name in uppercase
+    Constant(String, u64),
+    Array(Array),
+}
+
+pub(crate) struct Document {
+    // Prefix added to the names of every array and constant
+    pub prefix: String,
+    pub width: u32,
+    pub height: u32,
+    // Number of bits each pixel takes up in the image data
+    pub bits_per_pixel: u8,
+    // Length of the shared palette the image data indexes into, if it uses
+    // one instead of a palette of its own
+    pub shared_palette_len: Option<usize>,
+    pub items: Vec<Item>,
+}
+
+impl Document {
+    pub(crate) fn new(width: u32, height: u32, prefix: &str) -> Self {
+        Document { prefix: String::from(prefix), width, height,
+            bits_per_pixel: 0, shared_palette_len: None, items: Vec::new() }
+    }
+
+    // Returns the name in lowercase with the prefix added as it was given
+    fn symbol(&self, name: &str) -> String {
+        match self.prefix.is_empty() {
+            true => name.to_lowercase(),
+            false => format!("{}_{}", self.prefix, name.to_lowercase()),
+        }
+    }
+
+    // Returns the name with the prefix added, in uppercase
+    fn constant(&self, name: &str) -> String {
+        self.symbol(name).to_uppercase()
+    }
+
+    pub(crate) fn push(&mut self, item: Item) {
+        self.items.push(item);
+    }
+
+    // Returns the constants describing the image: its dimensions, the length
+    // of the palette if there is one (its own or the shared one it indexes
+    // into) and the bits per pixel in the image data. Documents with just a
+    // palette only have the palette length.
+    fn metadata(&self) -> Vec<(String, u64)> {
+        let mut metadata: Vec<(String, u64)> = Vec::new();
+        let image = self.array("image_data").is_some();
+        if image {
+            metadata.push((self.constant("WIDTH"), u64::from(self.width)));
+            metadata.push((self.constant("HEIGHT"), u64::from(self.height)));
+        }
+        let palette_len = self.array("palette")
+            .map( |palette| palette.values.len() )
+            .or(self.shared_palette_len);
+        if let Some(len) = palette_len {
+            metadata.push((self.constant("PALETTE_LEN"), len as u64));
+        }
+        if image {
+            metadata.push((self.constant("BPP"),
+                u64::from(self.bits_per_pixel)));
+        }
+        metadata
+    }
+
+    // Returns the array with the given name, if there is one
+    pub(crate) fn array(&self, name: &str) -> Option<&Array> {
+        self.items.iter().find_map( |item| match item {
+            Item::Array(array) if array.name == name => Some(array),
+            _ => None,
+        })
+    }
+}
+
+// Writes the documents one after another in the configured language. C
+// output only has the definitions of the arrays if the declarations are
+// written to a separate header.
+pub(crate) fn write(config: &Config, documents: &[Document],
+    header_path: Option<&str>) -> Vec<u8> {
+    match config.language {
+        Language::C => match header_path {
+            Some(path) => write_c_source(config, documents, path).into_bytes(),
+            None => write_c(config, documents).into_bytes(),
+        },
+        Language::Rust => write_rust(documents).into_bytes(),
+        Language::Binary => documents.iter()
+            .flat_map( |document| write_binary(config, document) )
+            .collect(),
+    }
+}
+
+// Returns the C declaration of an array without the initializer or semicolon,
+// with the memory placement and alignment from the config
+fn c_declaration(config: &Config, document: &Document, array: &Array)
+    -> String {
+    let mut declaration = String::from("const ");
+    if let Some(qualifier) = config.placement.qualifier() {
+        declaration.push_str(format!("{} ", qualifier).as_str());
+    }
+    declaration.push_str(format!("{} {}[{}]", array.element.c_type(),
+        document.symbol(&array.name), array.values.len()).as_str());
+    if let Some(attribute) = config.placement.attribute() {
+        declaration.push_str(format!(" {}", attribute).as_str());
+    }
+    if let Some(alignment) = config.alignment {
+        declaration.push_str(format!(" __attribute__((aligned({})))",
+            alignment).as_str());
+    }
+    declaration
+}
+
+fn write_c(config: &Config, documents: &[Document]) -> String {
+    let mut output = String::from("#include <stdint.h>\n");
+    for document in documents {
+        write_c_document(&mut output, config, document, true);
+    }
+    output
+}
+
+// Writes the metadata, comments and constants of a document along with
+// either the definitions or the extern declarations of its arrays
+fn write_c_document(output: &mut String, config: &Config, document: &Document,
+    definitions: bool) {
+    output.push('\n');
+    for (name, value) in document.metadata() {
+        output.push_str(format!("#define {} {}\n", name, value).as_str());
+    }
+    for item in &document.items {
+        match item {
+            Item::Comment(text) => output.push_str(format!("\n// {}\n",
+                text).as_str()),
+            Item::Constant(name, value) => output.push_str(format!(
+                "\n#define {} {}\n", document.constant(name), value).as_str()),
+            Item::Array(array) => {
+                output.push('\n');
+                if let Some(comment) = &array.comment {
+                    output.push_str(format!("// {}\n", comment).as_str());
+                }
+                match definitions {
+                    true => {
+                        output.push_str(format!("{} = {{\n",
+                            c_declaration(config, document, array)).as_str());
+                        write_values(output, array);
+                        output.push_str("};\n");
+                    },
+                    false => output.push_str(format!("extern {};\n",
+                        c_declaration(config, document, array)).as_str()),
+                }
+            },
+        }
+    }
+}
+
+// Writes the declarations of everything in the documents as a C header to go
+// with a source file written by write_c_source, including another header at
+// the given path if there is one
+pub(crate) fn write_c_header(config: &Config, documents: &[Document],
+    path: &str, include: Option<&str>) -> String {
+    // Build the include guard out of the file name
+    let guard: String = file_name(path).chars()
+        .map( |c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+    let mut output = format!("#ifndef {0}\n#define {0}\n\n\
+        #include <stdint.h>\n", guard);
+    if let Some(include) = include {
+        output.push_str(format!("#include \"{}\"\n", file_name(include))
+            .as_str());
+    }
+    for document in documents {
+        write_c_document(&mut output, config, document, false);
+    }
+    output.push_str(format!("\n#endif // {}\n", guard).as_str());
+    output
+}
+
+// Writes the definitions of the arrays in the documents as a C source file
+// including the header at the given path
+fn write_c_source(config: &Config, documents: &[Document], header_path: &str)
+    -> String {
+    let mut output = format!("#include \"{}\"\n", file_name(header_path));
+    for document in documents {
+        for item in &document.items {
+            if let Item::Array(array) = item {
+                output.push_str(format!("\n{} = {{\n",
+                    c_declaration(config, document, array)).as_str());
+                write_values(&mut output, array);
+                output.push_str("};\n");
+            }
+        }
+    }
+    output
+}
+
+// Returns the file name part of a path
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name()
+        .map_or_else( || String::from(path),
+            |name| name.to_string_lossy().into_owned() )
+}
+
+fn write_rust(documents: &[Document]) -> String {
+    let mut output = String::new();
+    for (i, document) in documents.iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+        for (name, value) in document.metadata() {
+            output.push_str(format!("pub const {}: usize = {};\n", name,
+                value).as_str());
+        }
+        for item in &document.items {
+            match item {
+                Item::Comment(text) => output.push_str(format!("\n// {}\n",
+                    text).as_str()),
+                Item::Constant(name, value) => output.push_str(format!(
+                    "\npub const {}: usize = {};\n", document.constant(name),
+                    value).as_str()),
+                Item::Array(array) => {
+                    output.push('\n');
+                    if let Some(comment) = &array.comment {
+                        output.push_str(format!("// {}\n", comment)
+                            .as_str());
+                    }
+                    output.push_str(format!("pub static {}: [{}; {}] = [\n",
+                        document.constant(&array.name),
+                        array.element.rust_type(),
+                        array.values.len()).as_str());
+                    write_values(&mut output, array);
+                    output.push_str("];\n");
+                },
+            }
+        }
+    }
+    output
+}
+
+// Writes the palette, image data and mask one after another as bytes in the
+// configured byte order, optionally after a header describing the data:
+//
+//     offset  size  contents
+//          0     4  "IMGA"
+//          4     4  width
+//          8     4  height
+//         12     4  palette length (0 if there's no palette)
+//         16     1  colour format id
+//         17     1  bits per pixel in the image data
+//         18     1  flags (bit 0: mask follows the image data, bit 1: rows
+//                   are padded to whole bytes, bit 2: packed pixels are in
+//                   LSB first order)
+//         19     1  reserved (0)
+fn write_binary(config: &Config, document: &Document) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::new();
+    let palette = document.array("palette");
+    let image_data = document.array("image_data");
+    let mask = document.array("image_mask");
+
+    if config.binary_header {
+        output.extend_from_slice(BINARY_MAGIC);
+        write_number(&mut output, config.endianness, 4, document.width);
+        write_number(&mut output, config.endianness, 4, document.height);
+        write_number(&mut output, config.endianness, 4,
+            palette.map_or(0, |array| array.values.len() as u32));
+        output.push(config.colour_format.id());
+        output.push(document.bits_per_pixel);
+        let mut flags = 0u8;
+        if mask.is_some() {
+            flags |= 1;
+        }
+        if config.pad_rows {
+            flags |= 1 << 1;
+        }
+        if let crate::BitOrder::LsbFirst = config.bit_order {
+            flags |= 1 << 2;
+        }
+        output.push(flags);
+        output.push(0);
+    }
+
+    for array in [palette, image_data, mask].iter().flatten() {
+        for &value in &array.values {
+            write_number(&mut output, config.endianness,
+                array.element.bytes(), value);
+        }
+    }
+    output
+}
+
+// Writes the lowest bytes of a number in the given byte order
+fn write_number(output: &mut Vec<u8>, endianness: Endianness, bytes: usize,
+    value: u32) {
+    match endianness {
+        Endianness::Little => output.extend_from_slice(
+            &value.to_le_bytes()[..bytes]),
+        Endianness::Big => output.extend_from_slice(
+            &value.to_be_bytes()[4 - bytes..]),
+    }
+}
+
+// Writes the values of an array, wrapping lines at 80 columns
+fn write_values(output: &mut String, array: &Array) {
+    let mut line = String::from("    ");
+    let mut to_add: String;
+    for value in &array.values {
+        to_add = match array.hex_digits {
+            Some(digits) => format!("{:#0width$X},", value,
+                width = digits + 2),
+            None => format!("{},", value),
+        };
+        if array.spaced {
+            to_add.push(' ');
+        }
+        // Check if we need to push the current value to the next line
+        if line.len() + to_add.len() > 80 {
+            output.push_str(format!("{}\n", line).as_str());
+            line = String::from("    ");
+        }
+
+        line.push_str(to_add.as_str());
+    }
+    if !line.trim().is_empty() {
+        output.push_str(format!("{}\n", line).as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ BitOrder, ColourFormat };
+
+    fn array(name: &str, element: Element, values: Vec<u32>) -> Item {
+        Item::Array(Array { name: String::from(name), element, values,
+            hex_digits: None, spaced: false, comment: None })
+    }
+
+    #[test]
+    fn symbols() {
+        let document = Document::new(1, 1, "MyHeart");
+        assert_eq!(document.symbol("image_data"), "MyHeart_image_data");
+        assert_eq!(document.constant("WIDTH"), "MYHEART_WIDTH");
+        let document = Document::new(1, 1, "");
+        assert_eq!(document.symbol("palette"), "palette");
+        assert_eq!(document.constant("palette_len"), "PALETTE_LEN");
+    }
+
+    #[test]
+    fn binary_header() {
+        let mut document = Document::new(0x12345, 7, "heart");
+        document.bits_per_pixel = 4;
+        document.push(array("palette", Element::U16, vec![0xB192; 70000]));
+        document.push(array("image_data", Element::U8, vec![0x21, 0x43]));
+        document.push(array("image_mask", Element::U8, vec![0xFF]));
+        let config = Config {
+            binary_header: true,
+            pad_rows: true,
+            bit_order: BitOrder::LsbFirst,
+            ..Config::default()
+        };
+
+        let output = write_binary(&config, &document);
+        assert_eq!(output.len(), 20 + 70000 * 2 + 2 + 1);
+        assert_eq!(&output[..20], &[
+            b'I', b'M', b'G', b'A',
+            0x45, 0x23, 0x01, 0x00,
+            0x07, 0x00, 0x00, 0x00,
+            0x70, 0x11, 0x01, 0x00,
+            ColourFormat::RGB565.id(), 4, 0b111, 0,
+        ]);
+        assert_eq!(&output[20..22], &[0x92, 0xB1]);
+        assert_eq!(&output[output.len() - 3..], &[0x21, 0x43, 0xFF]);
+
+        let config = Config {
+            binary_header: true,
+            endianness: Endianness::Big,
+            colour_format: ColourFormat::RGB,
+            ..Config::default()
+        };
+        let output = write_binary(&config, &document);
+        assert_eq!(&output[..20], &[
+            b'I', b'M', b'G', b'A',
+            0x00, 0x01, 0x23, 0x45,
+            0x00, 0x00, 0x00, 0x07,
+            0x00, 0x01, 0x11, 0x70,
+            ColourFormat::RGB.id(), 4, 0b001, 0,
+        ]);
+        assert_eq!(&output[20..22], &[0xB1, 0x92]);
+    }
+
+    #[test]
+    fn binary_without_header() {
+        let mut document = Document::new(2, 1, "");
+        document.push(array("image_data", Element::U32, vec![0xFF8000, 1]));
+        let output = write_binary(&Config::default(), &document);
+        assert_eq!(output, vec![0x00, 0x80, 0xFF, 0x00, 0x01, 0x00, 0x00,
+            0x00]);
+    }
+}
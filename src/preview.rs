@@ -0,0 +1,93 @@
+use crate::{ BitOrder, Config };
+use crate::colour::Rgb;
+
+// The arrays of a converted image, as written to the output
+pub(crate) struct Arrays<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u8,
+    pub palette: Option<&'a [u32]>,
+    pub image_data: &'a [u32],
+    pub mask: Option<&'a [u32]>,
+    pub transparent_index: Option<usize>,
+}
+
+// Renders the image data the way the device will show it: palette indices
+// resolved to their palette entries, or colour values without a palette,
+// decoded from the colour format back to 8 bit channels. Pixels are
+// transparent where the transparent index or the mask says so.
+pub(crate) fn render(config: &Config, arrays: &Arrays) -> image::RgbaImage {
+    let Arrays { width, height, bits_per_pixel, .. } = *arrays;
+    let decode = |value: u32| config.colour_format.decode_ordered(value,
+        config.channel_order, config.byte_order);
+    let values = match bits_per_pixel < 8 {
+        true => unpack(config, arrays.image_data, bits_per_pixel, width,
+            height),
+        false => arrays.image_data.to_vec(),
+    };
+    let mask = arrays.mask.map( |mask| unpack(config, mask, 1, width,
+        height) );
+    let colours: Option<Vec<Rgb>> = arrays.palette.map( |palette| palette.iter()
+        .map( |&value| decode(value) ).collect() );
+
+    image::RgbaImage::from_fn(width, height, |x, y| {
+        let i = (y * width + x) as usize;
+        let value = values.get(i).copied().unwrap_or(0);
+        let (colour, transparent) = match &colours {
+            Some(colours) => (colours.get(value as usize).copied()
+                .unwrap_or(Rgb(0)),
+                Some(value as usize) == arrays.transparent_index),
+            None => (decode(value), false),
+        };
+        let masked = mask.as_ref()
+            .is_some_and( |mask| mask.get(i) == Some(&0) );
+        let alpha = match (transparent || masked,
+            config.colour_format.has_alpha()) {
+            (true, _) => 0,
+            (false, true) => colour.a(),
+            (false, false) => 255,
+        };
+        image::Rgba([colour.r(), colour.g(), colour.b(), alpha])
+    })
+}
+
+// Scales the image up by a whole number factor, repeating every pixel
+pub(crate) fn scale(img: image::RgbaImage, factor: u32) -> image::RgbaImage {
+    match factor {
+        1 => img,
+        _ => {
+            let (width, height) = img.dimensions();
+            image::imageops::resize(&img, width * factor, height * factor,
+                image::FilterType::Nearest)
+        },
+    }
+}
+
+// Unpacks a value of the given number of bits for every pixel from bytes in
+// the configured bit order, skipping the padding at the end of rows if rows
+// are padded to whole bytes
+pub(crate) fn unpack(config: &Config, bytes: &[u32], bits: u8, width: u32,
+    height: u32) -> Vec<u32> {
+    let bits = u32::from(bits);
+    let per_byte = 8 / bits;
+    let mut values: Vec<u32> = Vec::new();
+    let mut byte = 0;
+    let mut count: u32 = 0;
+    for _ in 0..height {
+        for x in 0..width {
+            let shift = match config.bit_order {
+                BitOrder::MsbFirst => 8 - bits * (count + 1),
+                BitOrder::LsbFirst => bits * count,
+            };
+            let current = bytes.get(byte).copied().unwrap_or(0);
+            values.push(current >> shift & ((1 << bits) - 1));
+            count += 1;
+
+            if count == per_byte || (config.pad_rows && x == width - 1) {
+                byte += 1;
+                count = 0;
+            }
+        }
+    }
+    values
+}
@@ -0,0 +1,218 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use crate::colour::{ Rgb, is_transparent };
+
+// Method used to reduce the number of colours in an image
+#[derive(Debug, Clone, Copy)]
+pub enum Quantizer { MedianCut, Octree }
+
+// Picks at most the given number of colours with the given method to
+// represent all of the images together. Transparent pixels (if an alpha
+// threshold is given) don't count towards the colours.
+pub fn quantize(images: &[image::DynamicImage], method: Quantizer,
+    colours: usize, alpha_threshold: Option<u8>) -> Vec<Rgb> {
+    // Count how many times each colour appears in the images, so that colours
+    // covering large areas get more weight when picking the new colours
+    let mut histogram: HashMap<Rgb, u64> = HashMap::new();
+    for img in images {
+        for pixel in img.to_bgra().pixels() {
+            if is_transparent(pixel, alpha_threshold) {
+                continue
+            }
+            *histogram.entry(Rgb::from(pixel)).or_insert(0) += 1;
+        }
+    }
+    let histogram: Vec<(Rgb, u64)> = histogram.into_iter().collect();
+
+    match method {
+        Quantizer::MedianCut => median_cut(histogram, colours),
+        Quantizer::Octree => octree(&histogram, colours),
+    }
+}
+
+// Returns the weighted average of the given colours
+fn average(colours: &[(Rgb, u64)]) -> Rgb {
+    let (mut r, mut g, mut b, mut total) = (0u64, 0u64, 0u64, 0u64);
+    for (colour, count) in colours {
+        r += u64::from(colour.r()) * count;
+        g += u64::from(colour.g()) * count;
+        b += u64::from(colour.b()) * count;
+        total += count;
+    }
+    let total = total.max(1);
+    Rgb::new((r / total) as u8, (g / total) as u8, (b / total) as u8)
+}
+
+// Median cut: starting with a box containing every colour, repeatedly split
+// the box with the widest range of values in a single channel at the median
+// of that channel, until there are as many boxes as colours wanted. Each box
+// then becomes the average of its colours.
+fn median_cut(histogram: Vec<(Rgb, u64)>, colours: usize) -> Vec<Rgb> {
+    let channel = |colour: &Rgb, c: usize| match c {
+        0 => colour.r(),
+        1 => colour.g(),
+        _ => colour.b(),
+    };
+    // Returns the channel with the widest range in the box, and the range
+    let widest = |colours: &[(Rgb, u64)]| -> (usize, u8) {
+        (0..3).map( |c| {
+            let min = colours.iter().map( |(rgb, _)| channel(rgb, c) ).min();
+            let max = colours.iter().map( |(rgb, _)| channel(rgb, c) ).max();
+            (c, max.unwrap_or(0) - min.unwrap_or(0))
+        }).max_by_key( |&(_, range)| range ).unwrap()
+    };
+
+    let mut boxes: Vec<Vec<(Rgb, u64)>> = vec![histogram];
+    while boxes.len() < colours {
+        // Find the box to split, if any can still be split
+        let to_split = boxes.iter().enumerate()
+            .filter( |(_, b)| b.len() > 1 )
+            .max_by_key( |(_, b)| widest(b).1 )
+            .map( |(index, _)| index );
+        let index = match to_split {
+            Some(index) => index,
+            None => break,
+        };
+
+        // Split at the pixel-weighted median of the widest channel, making
+        // sure both halves get at least one colour
+        let mut current = boxes.swap_remove(index);
+        let (c, _) = widest(&current);
+        current.sort_by_key( |(rgb, _)| channel(rgb, c) );
+        let total: u64 = current.iter().map( |(_, count)| count ).sum();
+        let mut seen = 0;
+        let mut split = 1;
+        for (i, (_, count)) in current.iter().enumerate() {
+            seen += count;
+            if seen * 2 >= total {
+                split = i + 1;
+                break
+            }
+        }
+        let split = split.min(current.len() - 1);
+        let upper = current.split_off(split);
+        boxes.push(current);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map( |b| average(b) ).collect()
+}
+
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    r: u64,
+    g: u64,
+    b: u64,
+    count: u64,
+    leaf: bool,
+}
+
+impl OctreeNode {
+    fn new() -> Self {
+        OctreeNode { children: [None; 8], r: 0, g: 0, b: 0, count: 0,
+            leaf: false }
+    }
+}
+
+// Octree: every colour is inserted into a tree where each level splits on the
+// next most significant bit of red, green and blue. Nodes at the deepest level
+// are then merged into their parents (least used first) until the number of
+// leaves is small enough. Each leaf then becomes the average of its colours.
+fn octree(histogram: &[(Rgb, u64)], colours: usize) -> Vec<Rgb> {
+    let colours = colours.max(1);
+    let mut nodes: Vec<OctreeNode> = vec![OctreeNode::new()];
+    // Nodes with children on each level, for finding nodes to merge
+    let mut levels: Vec<Vec<usize>> = vec![Vec::new(); 8];
+    let mut leaves = 0;
+
+    for &(colour, count) in histogram {
+        let mut node = 0;
+        for (level, reducible) in levels.iter_mut().enumerate() {
+            let shift = 7 - level;
+            let child = (((colour.r() >> shift) & 1) << 2
+                | ((colour.g() >> shift) & 1) << 1
+                | ((colour.b() >> shift) & 1)) as usize;
+            node = match nodes[node].children[child] {
+                Some(next) => next,
+                None => {
+                    if nodes[node].children.iter().all(Option::is_none) {
+                        reducible.push(node);
+                    }
+                    nodes.push(OctreeNode::new());
+                    let next = nodes.len() - 1;
+                    nodes[node].children[child] = Some(next);
+                    next
+                },
+            };
+        }
+        let leaf = &mut nodes[node];
+        if !leaf.leaf {
+            leaf.leaf = true;
+            leaves += 1;
+        }
+        leaf.r += u64::from(colour.r()) * count;
+        leaf.g += u64::from(colour.g()) * count;
+        leaf.b += u64::from(colour.b()) * count;
+        leaf.count += count;
+    }
+
+    // Merge nodes, starting from the deepest level and the least used nodes
+    // on each level, until few enough leaves remain. The root isn't merged
+    // since that would leave a single colour.
+    for level in (1..8).rev() {
+        if leaves <= colours {
+            break
+        }
+        let mut reducible = std::mem::take(&mut levels[level]);
+        reducible.sort_by_key( |&node| Reverse(subtree_count(&nodes, node)) );
+        while leaves > colours {
+            let node = match reducible.pop() {
+                Some(node) => node,
+                None => break,
+            };
+            let children = nodes[node].children;
+            for child in children.iter().flatten() {
+                let (r, g, b, count) = (nodes[*child].r, nodes[*child].g,
+                    nodes[*child].b, nodes[*child].count);
+                let parent = &mut nodes[node];
+                parent.r += r;
+                parent.g += g;
+                parent.b += b;
+                parent.count += count;
+                leaves -= 1;
+            }
+            let parent = &mut nodes[node];
+            parent.children = [None; 8];
+            parent.leaf = true;
+            leaves += 1;
+        }
+    }
+
+    // Collect the averages of the remaining leaves
+    let mut palette: Vec<(Rgb, u64)> = Vec::new();
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let node = &nodes[node];
+        if node.leaf {
+            let count = node.count.max(1);
+            palette.push((Rgb::new((node.r / count) as u8,
+                (node.g / count) as u8, (node.b / count) as u8), node.count));
+        } else {
+            stack.extend(node.children.iter().flatten());
+        }
+    }
+
+    // With fewer colours wanted than there are branches under the root, the
+    // remaining leaves still need to be reduced further
+    if palette.len() > colours {
+        return median_cut(palette, colours)
+    }
+    palette.into_iter().map( |(colour, _)| colour ).collect()
+}
+
+// Returns the number of pixels under the given octree node
+fn subtree_count(nodes: &[OctreeNode], node: usize) -> u64 {
+    nodes[node].count + nodes[node].children.iter().flatten()
+        .map( |&child| subtree_count(nodes, child) )
+        .sum::<u64>()
+}
@@ -0,0 +1,363 @@
+use std::fs;
+use crate::{ Config, Error, PaletteSize, preview };
+use crate::output::Element;
+
+// The macros and arrays defined in a C source file
+struct Source {
+    defines: Vec<(String, u64)>,
+    arrays: Vec<SourceArray>,
+}
+
+// An array defined in a C source file, with its element type if it's one of
+// the types the converter writes
+struct SourceArray {
+    name: String,
+    element: Option<Element>,
+    values: Vec<u32>,
+}
+
+impl Source {
+    fn define(&self, name: &str) -> Option<u64> {
+        self.defines.iter().find( |(n, _)| n == name ).map( |(_, v)| *v )
+    }
+
+    fn array(&self, name: &str) -> Option<&SourceArray> {
+        self.arrays.iter().find( |array| array.name == name )
+    }
+}
+
+// Reads the palette, image data and mask arrays of an image back out of a C
+// file written by the converter and writes the image they make up to a PNG.
+// The dimensions and bits per pixel come from the macros written with the
+// arrays if there are any, or otherwise from the config, which also has to
+// match the colour format and packing the file was written with. Returns a
+// note describing the image read.
+pub(crate) fn convert(config: &Config) -> Result<Vec<String>, Error> {
+    let path = &config.inputs[0].path;
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => return Err(Error::Read { path: path.clone(), error }),
+    };
+    let fail = |message: String| Error::Source { path: path.clone(),
+        message };
+    let source = parse(&text).map_err(fail)?;
+
+    // Find the image data of the image asked for, or of the first image
+    let prefix = match config.inputs[0].prefix.as_str() {
+        "" => match source.arrays.iter()
+            .find( |array| array.name.ends_with("image_data") ) {
+            Some(array) => array.name.trim_end_matches("image_data")
+                .trim_end_matches('_').to_string(),
+            None => return Err(fail(String::from("No image_data array \
+                found"))),
+        },
+        prefix => String::from(prefix),
+    };
+    let symbol = |name: &str| match prefix.is_empty() {
+        true => String::from(name),
+        false => format!("{}_{}", prefix, name),
+    };
+    let constant = |name: &str| symbol(name).to_uppercase();
+    let image_data = match source.array(&symbol("image_data")) {
+        Some(array) => array,
+        None => return Err(fail(format!("No array named {}",
+            symbol("image_data")))),
+    };
+
+    // Use the image's own palette, or the shared one if it doesn't have one.
+    // Images with a BPP macro but no PALETTE_LEN have raw colour values.
+    let palette = match (config.no_palette, source.array(&symbol("palette"))) {
+        (true, _) => None,
+        (false, Some(palette)) => Some(palette),
+        (false, None) => match (source.define(&constant("BPP")),
+            source.define(&constant("PALETTE_LEN"))) {
+            (Some(_), None) => None,
+            _ => source.arrays.iter().find( |array| is_shared(&source, array) ),
+        },
+    };
+    let mask = source.array(&symbol("image_mask"));
+
+    // The colour values have to be in the type the colour format is written
+    // with, otherwise the file was written with a different format
+    let colours = palette.unwrap_or(image_data);
+    let element = config.colour_format.element();
+    if let Some(found) = colours.element.filter( |&found| found != element ) {
+        return Err(fail(format!("{} has {} values, but {:?} colours are \
+            written as {}, give the colour format the file was written with",
+            colours.name, found.c_type(), config.colour_format,
+            element.c_type())))
+    }
+    let (palette, mask) = (palette.map( |array| array.values.as_slice() ),
+        mask.map( |array| array.values.as_slice() ));
+    let image_data = image_data.values.as_slice();
+
+    let width = match (&config.width, source.define(&constant("WIDTH"))) {
+        (Some(width), _) => match width.parse::<u64>() {
+            Ok(width) => width,
+            Err(_) => match source.define(width) {
+                Some(width) => width,
+                None => return Err(fail(format!("No macro named {}", width))),
+            },
+        },
+        (None, Some(width)) => width,
+        (None, None) => return Err(fail(format!("No {} macro found, give the \
+            width with --width", constant("WIDTH")))),
+    };
+    if width == 0 {
+        return Err(fail(String::from("The width can't be 0")))
+    }
+
+    // Values without a palette are always one per pixel. Indices are packed
+    // into bytes with palette sizes of less than 8 bits.
+    let bits = match (palette, source.define(&constant("BPP")),
+        config.palette_size) {
+        (None, _, _) => 8,
+        (Some(_), Some(bits), _) => bits.max(1),
+        (Some(_), None, PaletteSize::Bits(bits)) => u64::from(bits),
+        (Some(_), None, PaletteSize::Auto) => 8,
+    };
+    let len = image_data.len() as u64;
+    let (packed, padded) = (bits < 8, config.pad_rows);
+    let row_bytes = (width * bits).div_ceil(8);
+    let height = match source.define(&constant("HEIGHT")) {
+        Some(height) => height,
+        None => match (packed, padded) {
+            (true, true) => len / row_bytes,
+            (true, false) => len * 8 / bits / width,
+            (false, _) => len / width,
+        },
+    };
+    let needed = match (packed, padded) {
+        (true, true) => row_bytes * height,
+        (true, false) => (width * height * bits).div_ceil(8),
+        (false, _) => width * height,
+    };
+    if height == 0 || needed > len {
+        return Err(fail(format!("{} has too few values for a width of {}",
+            symbol("image_data"), width)))
+    }
+
+    let arrays = preview::Arrays {
+        width: width as u32,
+        height: height as u32,
+        bits_per_pixel: bits as u8,
+        palette,
+        image_data,
+        mask,
+        transparent_index: source.define(&constant("TRANSPARENT_INDEX"))
+            .map( |index| index as usize ).or(config.transparent_index),
+    };
+    let img = preview::render(config, &arrays);
+    let output = &config.output_path;
+    preview::scale(img, config.preview_scale)
+        .save_with_format(output, image::ImageFormat::PNG)
+        .map_err( |error| Error::Write { path: Some(output.clone()),
+            error } )?;
+
+    Ok(vec![format!("Read {}x{} image from {}", width, height,
+        symbol("image_data"))])
+}
+
+// Returns whether the array is a shared palette: either one named just
+// palette, or one with a prefix that no image data has
+fn is_shared(source: &Source, array: &SourceArray) -> bool {
+    match array.name.as_str() {
+        "palette" => true,
+        name => match name.strip_suffix("_palette") {
+            Some(prefix) => source.array(&format!("{}_image_data", prefix))
+                .is_none(),
+            None => false,
+        },
+    }
+}
+
+// Parses the macros with integer values and the initialized arrays out of C
+// source, ignoring everything else
+fn parse(text: &str) -> Result<Source, String> {
+    let text = strip_comments(text);
+    let mut source = Source { defines: Vec::new(), arrays: Vec::new() };
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("#define") {
+            continue
+        }
+        if let (Some(name), Some(value)) = (words.next(), words.next()) {
+            if let Some(value) = parse_integer(value) {
+                source.defines.push((String::from(name), value));
+            }
+        }
+    }
+
+    // Arrays are named right before the [ of their declaration and have
+    // their values between braces after the =
+    let mut rest = text.as_str();
+    while let Some(open) = rest.find('{') {
+        let close = match rest[open..].find('}') {
+            Some(close) => open + close,
+            None => return Err(String::from("Unterminated array")),
+        };
+        let declaration = &rest[..open];
+        let declaration = declaration.rsplit(';').next().unwrap_or_default();
+        let start = declaration.rfind('=')
+            .and_then( |equals| declaration[..equals].find('[') )
+            .map( |bracket| declaration[..bracket].trim_end() );
+        if let Some(start) = start {
+            let mut words = start
+                .rsplit( |c: char| !(c.is_ascii_alphanumeric() || c == '_') );
+            let name = words.next().unwrap_or_default();
+            let element = words.find_map(Element::from_c_type);
+            let mut values: Vec<u32> = Vec::new();
+            for value in rest[open + 1..close].split(',').map(str::trim)
+                .filter( |v| !v.is_empty() ) {
+                match parse_integer(value) {
+                    Some(v) => values.push(v as u32),
+                    None => return Err(format!("Invalid value {} in {}",
+                        value, name)),
+                }
+            }
+            source.arrays.push(SourceArray { name: String::from(name),
+                element, values });
+        }
+        rest = &rest[close + 1..];
+    }
+    Ok(source)
+}
+
+// Returns the text with // and /* */ comments replaced by spaces, keeping the
+// lines they're on
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and( |&c| c != '\n' ) {
+                    chars.next();
+                }
+            },
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        stripped.push('\n');
+                    }
+                    if last == '*' && c == '/' {
+                        break
+                    }
+                    last = c;
+                }
+                stripped.push(' ');
+            },
+            _ => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+// Parses a decimal or hexadecimal integer literal, ignoring any suffix
+fn parse_integer(literal: &str) -> Option<u64> {
+    let literal = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+    match literal.strip_prefix("0x").or_else( || literal.strip_prefix("0X") ) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => literal.parse::<u64>().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example heart as written by earlier versions, without a prefix or
+    // any macros
+    const LEGACY: &str = "#include <stdint.h>
+
+const uint16_t palette[4] PROGMEM = {
+        0x0000, 0xB192, 0xEA6E, 0xFE59, 
+};
+
+const uint8_t image_data[49] PROGMEM = {
+    0,2,2,0,2,2,0,2,3,2,2,2,2,2,2,2,2,2,2,2,2,1,2,2,2,2,2,1,0,1,2,2,2,1,0,0,0,1,
+    2,1,0,0,0,0,0,1,0,0,0,
+};
+";
+
+    #[test]
+    fn parse_legacy() {
+        let source = parse(LEGACY).unwrap();
+        assert!(source.defines.is_empty());
+        assert_eq!(source.arrays.len(), 2);
+
+        let palette = source.array("palette").unwrap();
+        assert_eq!(palette.element, Some(Element::U16));
+        assert_eq!(palette.values, vec![0x0000, 0xB192, 0xEA6E, 0xFE59]);
+        let image_data = source.array("image_data").unwrap();
+        assert_eq!(image_data.element, Some(Element::U8));
+        assert_eq!(image_data.values.len(), 49);
+        assert_eq!(&image_data.values[..8], &[0, 2, 2, 0, 2, 2, 0, 2]);
+    }
+
+    #[test]
+    fn parse_macros_and_types() {
+        let source = parse("#define HEART_WIDTH 7
+#define HEART_HEIGHT 0x7u
+#define HEART_NAME \"heart\"
+static const uint32_t heart_palette[2] = { 0xFF0000, 0 };
+uint8_t heart_image_data[] = { 1, 0, 1 }; /* { 5 } */
+const unsigned short other[1] = { 3 };
+int count = 3;
+").unwrap();
+        assert_eq!(source.define("HEART_WIDTH"), Some(7));
+        assert_eq!(source.define("HEART_HEIGHT"), Some(7));
+        assert_eq!(source.define("HEART_NAME"), None);
+        let names: Vec<&str> = source.arrays.iter()
+            .map( |array| array.name.as_str() ).collect();
+        assert_eq!(names, vec!["heart_palette", "heart_image_data", "other"]);
+        assert_eq!(source.array("heart_palette").unwrap().element,
+            Some(Element::U32));
+        assert_eq!(source.array("heart_image_data").unwrap().values,
+            vec![1, 0, 1]);
+        assert_eq!(source.array("other").unwrap().element, None);
+
+        assert_eq!(parse("uint8_t a[] = { 1, x };").err(),
+            Some(String::from("Invalid value x in a")));
+        assert_eq!(parse("uint8_t a[] = { 1, 2").err(),
+            Some(String::from("Unterminated array")));
+    }
+
+    #[test]
+    fn shared_palettes() {
+        let source = parse("uint16_t palette[1] = { 0 };
+uint16_t batch_palette[1] = { 0 };
+uint16_t heart_palette[1] = { 0 };
+uint8_t heart_image_data[1] = { 0 };
+uint16_t icon_image_data[1] = { 0 };
+").unwrap();
+        let shared: Vec<&str> = source.arrays.iter()
+            .filter( |array| is_shared(&source, array) )
+            .map( |array| array.name.as_str() ).collect();
+        assert_eq!(shared, vec!["palette", "batch_palette"]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(strip_comments("a // b { c }\nd"), "a \nd");
+        assert_eq!(strip_comments("a /* b\nc */ d"), "a \n  d");
+        assert_eq!(strip_comments("a /* b */"), "a  ");
+        assert_eq!(strip_comments("a / b * c"), "a / b * c");
+        assert_eq!(strip_comments("a /* b */ /* c **/ d"), "a     d");
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(parse_integer("42"), Some(42));
+        assert_eq!(parse_integer("0x2A"), Some(42));
+        assert_eq!(parse_integer("0X2a"), Some(42));
+        assert_eq!(parse_integer("0xFFFFFFFFu"), Some(0xFFFFFFFF));
+        assert_eq!(parse_integer("42UL"), Some(42));
+        assert_eq!(parse_integer("0x"), None);
+        assert_eq!(parse_integer("-1"), None);
+        assert_eq!(parse_integer("WIDTH"), None);
+        assert_eq!(parse_integer(""), None);
+    }
+}